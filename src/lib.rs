@@ -1,56 +1,637 @@
-use rand::Rng;
-use rhai::{Dynamic, Engine, EvalAltResult};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use rhai::{
+    AST, Array, Blob, Dynamic, Engine, EvalAltResult, FnPtr, Map, NativeCallContext, Scope,
+    format_map_as_json,
+};
 use sdl3::EventPump;
-use sdl3::event::Event;
-use sdl3::keyboard::Scancode;
-use sdl3::mouse::MouseButton;
-use sdl3::pixels::Color;
-use sdl3::rect::{Point, Rect};
-use sdl3::video::Window;
-use std::sync::{Arc, Mutex};
-use std::time::Duration;
+use sdl3::GamepadSubsystem;
+use sdl3::VideoSubsystem;
+use sdl3::audio::{AudioDevice, AudioFormat, AudioSpec, AudioStreamOwner};
+use sdl3::event::{Event, WindowEvent};
+use sdl3::gamepad::Gamepad;
+use sdl3::image::{LoadSurface, LoadTexture, SaveSurface};
+use sdl3::iostream::IOStream;
+use sdl3::keyboard::{Keycode, Scancode};
+use sdl3::messagebox::{MessageBoxFlag, show_simple_message_box};
+use sdl3::mouse::{Cursor, MouseButton};
+use sdl3::pixels::{Color, PixelFormat};
+use sdl3::rect::Rect;
+use sdl3::render::{BlendMode, Canvas, ClippingRect, FPoint, FRect, Texture, TextureCreator};
+use sdl3::surface::Surface;
+use sdl3::sys::blendmode::{
+    SDL_BLENDFACTOR_ONE, SDL_BLENDFACTOR_SRC_ALPHA, SDL_BLENDFACTOR_ZERO, SDL_BLENDOPERATION_ADD,
+    SDL_ComposeCustomBlendMode,
+};
+use sdl3::sys::events::{
+    SDL_EVENT_DROP_FILE, SDL_EVENT_LAST, SDL_EVENT_QUIT, SDL_FlushEvents, SDL_HasEvent,
+    SDL_SetEventEnabled,
+};
+use sdl3::sys::gamepad::{
+    SDL_Gamepad, SDL_GamepadHasSensor, SDL_GamepadSensorEnabled, SDL_GetGamepadFromID,
+    SDL_GetGamepadSensorData, SDL_GetGamepadTouchpadFinger, SDL_GetNumGamepadTouchpadFingers,
+    SDL_GetNumGamepadTouchpads, SDL_SetGamepadSensorEnabled,
+};
+use sdl3::sys::hints::{SDL_HINT_AUDIO_DEVICE_SAMPLE_FRAMES, SDL_ResetHint};
+use sdl3::sys::init::SDL_SetAppMetadata;
+use sdl3::sys::keyboard::SDL_SetTextInputArea;
+use sdl3::sys::keycode::SDL_KMOD_NONE;
+use sdl3::sys::pixels::{SDL_FColor, SDL_PixelFormat};
+use sdl3::sys::power::{
+    SDL_GetPowerInfo, SDL_POWERSTATE_CHARGED, SDL_POWERSTATE_CHARGING, SDL_POWERSTATE_ERROR,
+    SDL_POWERSTATE_NO_BATTERY, SDL_POWERSTATE_ON_BATTERY,
+};
+use sdl3::sys::rect::{SDL_FPoint, SDL_Rect};
+use sdl3::sys::render::{
+    SDL_GetRenderTarget, SDL_GetRenderVSync, SDL_GetTextureBlendMode,
+    SDL_LOGICAL_PRESENTATION_DISABLED, SDL_LOGICAL_PRESENTATION_INTEGER_SCALE,
+    SDL_LOGICAL_PRESENTATION_LETTERBOX, SDL_LOGICAL_PRESENTATION_OVERSCAN,
+    SDL_LOGICAL_PRESENTATION_STRETCH, SDL_RENDERER_VSYNC_ADAPTIVE, SDL_RENDERER_VSYNC_DISABLED,
+    SDL_RenderCoordinatesFromWindow, SDL_RenderCoordinatesToWindow, SDL_RenderGeometry,
+    SDL_RenderViewportSet, SDL_SetRenderTarget, SDL_SetRenderVSync, SDL_SetTextureBlendMode,
+    SDL_Vertex,
+};
+use sdl3::sys::sensor::{SDL_SENSOR_ACCEL, SDL_SENSOR_GYRO};
+use sdl3::sys::video::{
+    SDL_GetWindowSafeArea, SDL_SetWindowAlwaysOnTop, SDL_WINDOW_FULLSCREEN, SDL_WINDOW_HIDDEN,
+    SDL_WINDOW_INPUT_FOCUS, SDL_WINDOW_MAXIMIZED, SDL_WINDOW_MINIMIZED,
+    SDL_WINDOWPOS_CENTERED_MASK,
+};
+use sdl3::ttf::{Font, Sdl3TtfContext};
+use sdl3::video::{SystemTheme, Window, WindowContext};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::ffi::CString;
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant, SystemTime};
+
+// SDL's centered window position sentinel, exposed to scripts as
+// window_pos_centered()
+const WINDOW_POS_CENTERED: i32 = SDL_WINDOWPOS_CENTERED_MASK as i32;
+
+// Strings or colors that change every frame, like a timer, would otherwise grow
+// the text and glyph caches without bound, so each starts over once it holds
+// this many textures
+const TEXT_CACHE_LIMIT: usize = 256;
 
 // SDL3 context wrapper to be shared with Rhai
 pub struct SDLContext {
     sdl: sdl3::Sdl,
+    video: Option<VideoSubsystem>,
     window: Option<Window>,
-    canvas: Option<sdl3::render::Canvas<Window>>,
+    canvas: Option<Canvas<Window>>,
+    // Kept with the canvas so image files can be loaded straight into textures
+    texture_creator: Option<TextureCreator<WindowContext>>,
     event_pump: Option<EventPump>,
+    gamepad_subsystem: Option<GamepadSubsystem>,
+    // Loaded textures keyed by the handle returned to scripts
+    textures: HashMap<i64, Texture>,
+    next_texture_id: i64,
+    // TTF is initialized on first load_font and lives for the rest of the program
+    ttf: Option<&'static Sdl3TtfContext>,
+    fonts: HashMap<i64, Font<'static, 'static>>,
+    font_files: HashMap<String, &'static [u8]>,
+    next_font_id: i64,
+    // Frame limiter applied in present, 0 disables it
+    target_fps: u32,
+    last_present: Option<Instant>,
+    // One-shot callbacks scheduled with after, fired by run_loop
+    timers: Vec<(Instant, FnPtr)>,
+    // Left mouse button state at the last present, for click edge detection
+    mouse_left_at_present: bool,
+    // Frames presented so far, used to timestamp recorded input
+    frame_count: u64,
+    input_recorder: Option<InputRecorder>,
+    input_replay: Option<InputReplay>,
+    // Frame sequence capture started with start_capture
+    capture: Option<FrameCapture>,
+    particles: Vec<Particle>,
+    // Color run_loop clears to before each frame, None to keep the previous frame
+    auto_clear: Option<Color>,
+    // Playback stream for script-generated samples, opened with open_audio_stream
+    audio_stream: Option<AudioStreamOwner>,
+    // Camera and nested transforms, see Transform for which draws respect them
+    transform: Transform,
+    transform_stack: Vec<Transform>,
+    // Output device name chosen with open_audio_stream_on, None for the default
+    audio_device: Option<String>,
+    // Offset from set_origin, applied with the camera by draw_transform
+    origin: (f32, f32),
+    // Background image decodes started by load_texture_async, keyed by handle
+    pending_textures: HashMap<i64, JoinHandle<Result<DecodedImage, String>>>,
+    texture_load_errors: HashMap<i64, String>,
+    draw_stats: DrawStats,
+    gamepad: Option<Gamepad>,
+    layers: HashMap<i64, Texture>,
+    next_layer_id: i64,
+    active_layer: i64,
+    grids: HashMap<i64, SpatialGrid>,
+    next_grid_id: i64,
+    cursor: Option<Cursor>,
+    frame_budget_ms: f64,
+    slow_frames: u64,
+    worst_frame_ms: f64,
+    window_sized_layers: HashSet<i64>,
+    // Scenes from register_scene; goto_scene queues a switch that run_scenes
+    // applies, running the new scene's init, before the next frame
+    scenes: HashMap<String, Scene>,
+    active_scene: Option<String>,
+    next_scene: Option<String>,
+    // Scripts from compile_script and the scope run_compiled shares between them
+    compiled_scripts: HashMap<i64, AST>,
+    next_script_id: i64,
+    script_scope: Scope<'static>,
+    // Source files of scripts from watch_script and their last seen mtime
+    watched_scripts: HashMap<i64, (PathBuf, Option<SystemTime>)>,
+    // Shared with the engine's progress callback, see set_script_limits
+    script_limits: Arc<ScriptLimits>,
+    // Streaming texture reused by plot_pixels while the plot size stays the same
+    pixel_plot: Option<Texture>,
+    // Generator behind rand, shuffle, choose and particle spread, see seed_rng
+    rng: StdRng,
+    // Single-glyph textures for draw_text_styled, keyed by font, char and color,
+    // see TEXT_CACHE_LIMIT
+    glyph_cache: HashMap<(i64, char, [u8; 4]), Texture>,
+    // Whole-string textures for draw_text and friends, keyed by font, text and
+    // color so a score or menu label is rasterized once, see TEXT_CACHE_LIMIT
+    text_cache: HashMap<(i64, String, [u8; 4]), Texture>,
+    // Multiplier for get_relative_mouse_state deltas
+    relative_mouse_sensitivity: f64,
 }
 
 impl SDLContext {
     pub fn new() -> Result<Self, String> {
         let sdl = sdl3::init().map_err(|e| e.to_string())?;
-        Ok(SDLContext {
+        Ok(Self::with_sdl(sdl))
+    }
+
+    fn with_sdl(sdl: sdl3::Sdl) -> Self {
+        SDLContext {
             sdl,
+            video: None,
             window: None,
             canvas: None,
+            texture_creator: None,
             event_pump: None,
-        })
+            gamepad_subsystem: None,
+            textures: HashMap::new(),
+            next_texture_id: 1,
+            ttf: None,
+            fonts: HashMap::new(),
+            font_files: HashMap::new(),
+            next_font_id: 1,
+            target_fps: 0,
+            last_present: None,
+            timers: Vec::new(),
+            mouse_left_at_present: false,
+            frame_count: 0,
+            input_recorder: None,
+            input_replay: None,
+            capture: None,
+            particles: Vec::new(),
+            auto_clear: Some(Color::RGB(0, 0, 0)),
+            audio_stream: None,
+            transform: Transform::IDENTITY,
+            transform_stack: Vec::new(),
+            audio_device: None,
+            origin: (0.0, 0.0),
+            pending_textures: HashMap::new(),
+            texture_load_errors: HashMap::new(),
+            draw_stats: DrawStats::default(),
+            gamepad: None,
+            layers: HashMap::new(),
+            next_layer_id: 1,
+            active_layer: 0,
+            grids: HashMap::new(),
+            next_grid_id: 1,
+            cursor: None,
+            frame_budget_ms: 0.0,
+            slow_frames: 0,
+            worst_frame_ms: 0.0,
+            window_sized_layers: HashSet::new(),
+            scenes: HashMap::new(),
+            active_scene: None,
+            next_scene: None,
+            compiled_scripts: HashMap::new(),
+            next_script_id: 1,
+            script_scope: Scope::new(),
+            watched_scripts: HashMap::new(),
+            script_limits: Arc::new(ScriptLimits::new()),
+            pixel_plot: None,
+            rng: StdRng::from_os_rng(),
+            glyph_cache: HashMap::new(),
+            text_cache: HashMap::new(),
+            relative_mouse_sensitivity: 1.0,
+        }
+    }
+
+    // Tear down everything back to the state of a new context, so a restarted
+    // app can create its window and load assets again. Kept: the SDL handle,
+    // the TTF library and the font file cache, which are shared and leaked for
+    // the program's lifetime anyway. Everything else is cleared or closed:
+    // window, renderer, event pump, textures, layers, fonts, audio, gamepad,
+    // cursor, timers, scenes, compiled scripts, transforms, script limits and
+    // settings, and the random generator is reseeded.
+    fn reset(&mut self) {
+        self.free_textures();
+        self.script_limits.set(0, None);
+        let fresh = SDLContext {
+            ttf: self.ttf,
+            font_files: std::mem::take(&mut self.font_files),
+            script_limits: self.script_limits.clone(),
+            ..Self::with_sdl(self.sdl.clone())
+        };
+        *self = fresh;
+    }
+
+    // Textures have no destructor and die with their renderer, so everything
+    // made on the current canvas is freed before that canvas goes away
+    fn free_textures(&mut self) {
+        let textures = self
+            .textures
+            .drain()
+            .chain(self.layers.drain())
+            .map(|(_, texture)| texture)
+            .chain(self.pixel_plot.take())
+            .chain(self.glyph_cache.drain().map(|(_, texture)| texture))
+            .chain(self.text_cache.drain().map(|(_, texture)| texture));
+        for texture in textures {
+            // Safe: the canvas that owns the texture is still alive
+            unsafe { texture.destroy() };
+        }
+        self.window_sized_layers.clear();
+        self.active_layer = 0;
+    }
+
+    // A new window means a new renderer, so handles into the old one are dropped
+    fn install_canvas(&mut self, canvas: Canvas<Window>) {
+        self.free_textures();
+        self.window = Some(canvas.window().to_owned());
+        self.texture_creator = Some(canvas.texture_creator());
+        self.canvas = Some(canvas);
     }
 
     fn create_window(&mut self, title: &str, width: i32, height: i32) -> Result<(), String> {
-        let video = self.sdl.video().map_err(|e| e.to_string())?;
-        let window = video
+        self.create_window_at(
+            title,
+            WINDOW_POS_CENTERED,
+            WINDOW_POS_CENTERED,
+            width,
+            height,
+        )
+    }
+
+    fn video(&mut self) -> Result<&VideoSubsystem, String> {
+        if self.video.is_none() {
+            self.video = Some(self.sdl.video().map_err(|e| e.to_string())?);
+        }
+        Ok(self.video.as_ref().unwrap())
+    }
+
+    // Start video without opening a window, so displays can be queried first
+    fn init_video(&mut self) -> Result<(), String> {
+        self.video()?;
+        Ok(())
+    }
+
+    fn get_num_displays(&mut self) -> Result<i64, String> {
+        let displays = self.video()?.displays().map_err(|e| e.to_string())?;
+        Ok(displays.len() as i64)
+    }
+
+    // Desktop area of a display as [x, y, w, h], index counts from 0
+    fn get_display_bounds(&mut self, index: i64) -> Result<Array, String> {
+        let displays = self.video()?.displays().map_err(|e| e.to_string())?;
+        let display = usize::try_from(index)
+            .ok()
+            .and_then(|index| displays.get(index))
+            .ok_or_else(|| format!("Invalid display index: {}", index))?;
+        let bounds = display.get_bounds().map_err(|e| e.to_string())?;
+        Ok(vec![
+            Dynamic::from(bounds.x() as i64),
+            Dynamic::from(bounds.y() as i64),
+            Dynamic::from(bounds.width() as i64),
+            Dynamic::from(bounds.height() as i64),
+        ])
+    }
+
+    // x or y may be WINDOW_POS_CENTERED to center on that axis
+    fn create_window_at(
+        &mut self,
+        title: &str,
+        x: i32,
+        y: i32,
+        width: i32,
+        height: i32,
+    ) -> Result<(), String> {
+        let window = self
+            .video()?
             .window(title, width as u32, height as u32)
-            .position_centered()
+            .position(x, y)
             .build()
             .map_err(|e| e.to_string())?;
         let canvas = window.into_canvas();
-        self.window = Some(canvas.window().to_owned());
-        self.canvas = Some(canvas);
+        self.install_canvas(canvas);
+        Ok(())
+    }
+
+    // Centered window rendered with a specific driver from list_render_drivers,
+    // e.g. "software" to avoid depending on a GPU
+    fn create_window_ex(
+        &mut self,
+        title: &str,
+        width: i32,
+        height: i32,
+        driver: &str,
+    ) -> Result<(), String> {
+        if !sdl3::render::drivers().any(|name| name == driver) {
+            return Err(format!("Render driver not available: {}", driver));
+        }
+        let driver = CString::new(driver).map_err(|e| e.to_string())?;
+        let window = self
+            .video()?
+            .window(title, width as u32, height as u32)
+            .position(WINDOW_POS_CENTERED, WINDOW_POS_CENTERED)
+            .build()
+            .map_err(|e| e.to_string())?;
+        let canvas =
+            sdl3::render::create_renderer(window, Some(&driver)).map_err(|e| e.to_string())?;
+        self.install_canvas(canvas);
+        Ok(())
+    }
+
+    fn set_window_min_size(&mut self, w: u32, h: u32) -> Result<(), String> {
+        if let Some(window) = &mut self.window {
+            window.set_minimum_size(w, h).map_err(|e| e.to_string())
+        } else {
+            Err("Window not initialized".to_string())
+        }
+    }
+
+    fn set_window_max_size(&mut self, w: u32, h: u32) -> Result<(), String> {
+        if let Some(window) = &mut self.window {
+            window.set_maximum_size(w, h).map_err(|e| e.to_string())
+        } else {
+            Err("Window not initialized".to_string())
+        }
+    }
+
+    fn has_keyboard_focus(&self) -> Result<bool, String> {
+        if let Some(window) = &self.window {
+            Ok(window.has_input_focus())
+        } else {
+            Err("Window not initialized".to_string())
+        }
+    }
+
+    fn set_always_on_top(&mut self, on_top: bool) -> Result<(), String> {
+        if let Some(window) = &self.window {
+            if unsafe { SDL_SetWindowAlwaysOnTop(window.raw(), on_top) } {
+                Ok(())
+            } else {
+                Err(sdl3::get_error().to_string())
+            }
+        } else {
+            Err("Window not initialized".to_string())
+        }
+    }
+
+    fn minimize_window(&mut self) -> Result<(), String> {
+        if let Some(window) = &mut self.window {
+            window.minimize();
+            Ok(())
+        } else {
+            Err("Window not initialized".to_string())
+        }
+    }
+
+    fn maximize_window(&mut self) -> Result<(), String> {
+        if let Some(window) = &mut self.window {
+            window.maximize();
+            Ok(())
+        } else {
+            Err("Window not initialized".to_string())
+        }
+    }
+
+    fn restore_window(&mut self) -> Result<(), String> {
+        if let Some(window) = &mut self.window {
+            window.restore();
+            Ok(())
+        } else {
+            Err("Window not initialized".to_string())
+        }
+    }
+
+    // Confine the cursor to a rect in window coordinates, which must lie
+    // inside the window
+    fn set_mouse_rect(&mut self, x: i32, y: i32, w: i32, h: i32) -> Result<(), String> {
+        if let Some(window) = &self.window {
+            let (window_w, window_h) = window.size();
+            if w <= 0
+                || h <= 0
+                || x < 0
+                || y < 0
+                || x + w > window_w as i32
+                || y + h > window_h as i32
+            {
+                return Err(format!(
+                    "Mouse rect {}x{} at ({}, {}) is outside the {}x{} window",
+                    w, h, x, y, window_w, window_h
+                ));
+            }
+            window
+                .set_mouse_rect(Rect::new(x, y, w as u32, h as u32))
+                .map_err(|e| e.to_string())
+        } else {
+            Err("Window not initialized".to_string())
+        }
+    }
+
+    fn clear_mouse_rect(&mut self) -> Result<(), String> {
+        if let Some(window) = &self.window {
+            window.set_mouse_rect(None).map_err(|e| e.to_string())
+        } else {
+            Err("Window not initialized".to_string())
+        }
+    }
+
+    // Replace the mouse cursor with an image, clicking at (hot_x, hot_y)
+    // within it. The cursor is kept here since SDL frees it when dropped.
+    fn set_custom_cursor(&mut self, path: &str, hot_x: i32, hot_y: i32) -> Result<(), String> {
+        let surface =
+            Surface::from_file(path).map_err(|e| format!("Failed to load {}: {}", path, e))?;
+        let cursor = Cursor::from_surface(&surface, hot_x, hot_y).map_err(|e| e.to_string())?;
+        cursor.set();
+        self.cursor = Some(cursor);
         Ok(())
     }
 
-    fn set_draw_color(&mut self, r: u8, g: u8, b: u8) -> Result<(), String> {
+    // Deliver typed text as text_input events, including IME composition
+    // results, until stop_text_input. May show an on-screen keyboard.
+    fn start_text_input(&self) -> Result<(), String> {
+        if let Some(window) = &self.window {
+            window.subsystem().text_input().start(window);
+            Ok(())
+        } else {
+            Err("Window not initialized".to_string())
+        }
+    }
+
+    fn stop_text_input(&self) -> Result<(), String> {
+        if let Some(window) = &self.window {
+            window.subsystem().text_input().stop(window);
+            Ok(())
+        } else {
+            Err("Window not initialized".to_string())
+        }
+    }
+
+    // Area of the text field being typed into, in window coordinates, so the
+    // IME candidate window is placed next to it instead of over it
+    fn set_text_input_area(&self, x: i32, y: i32, w: i32, h: i32) -> Result<(), String> {
+        if let Some(window) = &self.window {
+            if !window.subsystem().text_input().is_active(window) {
+                return Err("Text input not started".to_string());
+            }
+            let area = SDL_Rect { x, y, w, h };
+            if unsafe { SDL_SetTextInputArea(window.raw(), &area, 0) } {
+                Ok(())
+            } else {
+                Err(sdl3::get_error().to_string())
+            }
+        } else {
+            Err("Window not initialized".to_string())
+        }
+    }
+
+    fn get_window_flags(&self) -> Result<Map, String> {
+        if let Some(window) = &self.window {
+            let flags = window.window_flags();
+            let has = |flag| Dynamic::from(flags & flag != 0);
+            let mut map = Map::new();
+            map.insert("minimized".into(), has(SDL_WINDOW_MINIMIZED));
+            map.insert("maximized".into(), has(SDL_WINDOW_MAXIMIZED));
+            map.insert("focused".into(), has(SDL_WINDOW_INPUT_FOCUS));
+            map.insert(
+                "shown".into(),
+                Dynamic::from(flags & SDL_WINDOW_HIDDEN == 0),
+            );
+            map.insert("fullscreen".into(), has(SDL_WINDOW_FULLSCREEN));
+            Ok(map)
+        } else {
+            Err("Window not initialized".to_string())
+        }
+    }
+
+    fn set_fullscreen(&mut self, fullscreen: bool) -> Result<(), String> {
+        if let Some(window) = &mut self.window {
+            window.set_fullscreen(fullscreen).map_err(|e| e.to_string())
+        } else {
+            Err("Window not initialized".to_string())
+        }
+    }
+
+    // Read from the window each time, so changes made by the OS (e.g. a
+    // fullscreen shortcut) are reflected
+    fn is_fullscreen(&self) -> Result<bool, String> {
+        if let Some(window) = &self.window {
+            Ok(window.window_flags() & SDL_WINDOW_FULLSCREEN != 0)
+        } else {
+            Err("Window not initialized".to_string())
+        }
+    }
+
+    // Window size in points, as [w, h]. On HiDPI displays a point covers
+    // several pixels, so this is smaller than get_window_pixel_size.
+    fn get_window_size(&self) -> Result<Array, String> {
+        if let Some(window) = &self.window {
+            let (w, h) = window.size();
+            Ok(vec![Dynamic::from(w as i64), Dynamic::from(h as i64)])
+        } else {
+            Err("Window not initialized".to_string())
+        }
+    }
+
+    // Drawable size in real pixels, as [w, h], from the renderer's output.
+    // Equal to get_window_size unless the display scales content (HiDPI).
+    fn get_window_pixel_size(&self) -> Result<Array, String> {
+        if let Some(canvas) = &self.canvas {
+            let (w, h) = canvas.output_size().map_err(|e| e.to_string())?;
+            Ok(vec![Dynamic::from(w as i64), Dynamic::from(h as i64)])
+        } else {
+            Err("Canvas not initialized".to_string())
+        }
+    }
+
+    // Area of the window not covered by notches or rounded corners, as
+    // [x, y, w, h] in points. On desktop this is the whole client area.
+    fn get_safe_area(&self) -> Result<Array, String> {
+        if let Some(window) = &self.window {
+            let mut rect = SDL_Rect::default();
+            if !unsafe { SDL_GetWindowSafeArea(window.raw(), &mut rect) } {
+                return Err(sdl3::get_error().to_string());
+            }
+            Ok(rect_to_array(Rect::new(
+                rect.x,
+                rect.y,
+                rect.w as u32,
+                rect.h as u32,
+            )))
+        } else {
+            Err("Window not initialized".to_string())
+        }
+    }
+
+    fn get_system_theme(&mut self) -> Result<String, String> {
+        self.video()?;
+        let theme = match VideoSubsystem::get_system_theme() {
+            SystemTheme::Light => "light",
+            SystemTheme::Dark => "dark",
+            SystemTheme::Unknown => "unknown",
+        };
+        Ok(theme.to_string())
+    }
+
+    // Blocking dialog with an OK button, type "info", "warning" or "error".
+    // Attached to the window when there is one; SDL can show it without
+    // video initialized, so it also works for reporting startup failures.
+    fn show_message_box(&self, title: &str, message: &str, kind: &str) -> Result<(), String> {
+        let flags = match kind {
+            "info" => MessageBoxFlag::INFORMATION,
+            "warning" => MessageBoxFlag::WARNING,
+            "error" => MessageBoxFlag::ERROR,
+            _ => return Err(format!("Unknown message box type: {}", kind)),
+        };
+        show_simple_message_box(flags, title, message, self.window.as_ref())
+            .map_err(|e| e.to_string())
+    }
+
+    fn set_draw_color(&mut self, color: Color) -> Result<(), String> {
         if let Some(canvas) = &mut self.canvas {
-            canvas.set_draw_color(Color::RGB(r, g, b));
+            canvas.set_draw_color(color);
             Ok(())
         } else {
             Err("Canvas not initialized".to_string())
         }
     }
 
+    fn get_draw_color(&self) -> Result<Array, String> {
+        if let Some(canvas) = &self.canvas {
+            let color = canvas.draw_color();
+            Ok([color.r, color.g, color.b, color.a]
+                .into_iter()
+                .map(|channel| Dynamic::from(channel as i64))
+                .collect())
+        } else {
+            Err("Canvas not initialized".to_string())
+        }
+    }
+
     fn clear(&mut self) -> Result<(), String> {
         if let Some(canvas) = &mut self.canvas {
             canvas.clear();
@@ -60,11 +641,33 @@ impl SDLContext {
         }
     }
 
+    fn set_auto_clear(&mut self, enabled: bool, r: u8, g: u8, b: u8) {
+        self.auto_clear = enabled.then(|| Color::RGB(r, g, b));
+    }
+
+    fn auto_clear_frame(&mut self) -> Result<(), String> {
+        if let Some(color) = self.auto_clear {
+            if let Some(canvas) = &mut self.canvas {
+                let previous = canvas.draw_color();
+                canvas.set_draw_color(color);
+                canvas.clear();
+                canvas.set_draw_color(previous);
+                Ok(())
+            } else {
+                Err("Canvas not initialized".to_string())
+            }
+        } else {
+            Ok(())
+        }
+    }
+
     fn draw_rect(&mut self, x: i32, y: i32, w: i32, h: i32) -> Result<(), String> {
+        let transform = self.draw_transform();
         if let Some(canvas) = &mut self.canvas {
             canvas
-                .draw_rect(Rect::new(x, y, w as u32, h as u32).into())
+                .draw_rect(transform.rect(Rect::new(x, y, w as u32, h as u32).into()))
                 .map_err(|e| e.to_string())?;
+            self.draw_stats.rects += 1;
             Ok(())
         } else {
             Err("Canvas not initialized".to_string())
@@ -72,126 +675,5983 @@ impl SDLContext {
     }
 
     fn fill_rect(&mut self, x: i32, y: i32, w: i32, h: i32) -> Result<(), String> {
+        let transform = self.draw_transform();
         if let Some(canvas) = &mut self.canvas {
             canvas
-                .fill_rect(Rect::new(x, y, w as u32, h as u32))
+                .fill_rect(transform.rect(Rect::new(x, y, w as u32, h as u32).into()))
                 .map_err(|e| e.to_string())?;
+            self.draw_stats.rects += 1;
             Ok(())
         } else {
             Err("Canvas not initialized".to_string())
         }
     }
 
+    // Fill a rect with the draw color, its alpha multiplied by the mask
+    // texture's alpha (stretched over the rect). The fill is composed in a
+    // temporary render target, then blended onto the canvas.
+    fn fill_rect_masked(
+        &mut self,
+        x: i32,
+        y: i32,
+        w: i32,
+        h: i32,
+        mask: i64,
+    ) -> Result<(), String> {
+        let transform = self.draw_transform();
+        if let Some(canvas) = &mut self.canvas {
+            let mask = self
+                .textures
+                .get(&mask)
+                .ok_or_else(|| format!("Invalid texture handle: {}", mask))?;
+            let color = canvas.draw_color();
+            let mut target = canvas
+                .texture_creator()
+                .create_texture_target(
+                    pixel_format_from_name("RGBA32")?,
+                    w.max(1) as u32,
+                    h.max(1) as u32,
+                )
+                .map_err(|e| e.to_string())?;
+            // Keep the fill's color and scale its alpha by the mask's alpha
+            let mask_alpha = unsafe {
+                SDL_ComposeCustomBlendMode(
+                    SDL_BLENDFACTOR_ZERO,
+                    SDL_BLENDFACTOR_ONE,
+                    SDL_BLENDOPERATION_ADD,
+                    SDL_BLENDFACTOR_ZERO,
+                    SDL_BLENDFACTOR_SRC_ALPHA,
+                    SDL_BLENDOPERATION_ADD,
+                )
+            };
+            let mut previous_mode = 0;
+            let mut composed = Ok(());
+            unsafe {
+                SDL_GetTextureBlendMode(mask.raw(), &mut previous_mode);
+                SDL_SetTextureBlendMode(mask.raw(), mask_alpha);
+            }
+            let result = canvas.with_texture_canvas(&mut target, |target_canvas| {
+                target_canvas.set_draw_color(color);
+                target_canvas.clear();
+                composed = target_canvas
+                    .copy(mask, None, None)
+                    .map_err(|e| e.to_string());
+            });
+            unsafe { SDL_SetTextureBlendMode(mask.raw(), previous_mode) };
+            let result = result
+                .map_err(|e| e.to_string())
+                .and(composed)
+                .and_then(|_| {
+                    target.set_blend_mode(BlendMode::Blend);
+                    let dst = FRect::new(x as f32, y as f32, w as f32, h as f32);
+                    canvas
+                        .copy(&target, None, transform.rect(dst))
+                        .map_err(|e| e.to_string())
+                });
+            // Safe: the canvas that owns the texture is still alive
+            unsafe { target.destroy() };
+            if result.is_ok() {
+                self.draw_stats.rects += 1;
+            }
+            result
+        } else {
+            Err("Canvas not initialized".to_string())
+        }
+    }
+
     fn draw_point(&mut self, x: i32, y: i32) -> Result<(), String> {
+        let transform = self.draw_transform();
         if let Some(canvas) = &mut self.canvas {
             canvas
-                .draw_point(Point::new(x, y))
+                .draw_point(transform.point(x as f32, y as f32))
                 .map_err(|e| e.to_string())?;
+            self.draw_stats.points += 1;
             Ok(())
         } else {
             Err("Canvas not initialized".to_string())
         }
     }
 
+    // Points given as [x, y, r, g, b]. Consecutive points of the same color
+    // are drawn in one call and each color change costs another, so data
+    // grouped by color draws much faster than colors that alternate.
+    fn draw_colored_points(&mut self, data: &Array) -> Result<(), String> {
+        let transform = self.draw_transform();
+        if let Some(canvas) = &mut self.canvas {
+            let mut points = Vec::with_capacity(data.len());
+            for item in data {
+                let values = item
+                    .as_array_ref()
+                    .map_err(|_| "Expected [x, y, r, g, b] for each point".to_string())?;
+                if values.len() != 5 {
+                    return Err("Expected [x, y, r, g, b] for each point".to_string());
+                }
+                let coordinate = |value: &Dynamic| {
+                    value
+                        .as_float()
+                        .or_else(|_| value.as_int().map(|v| v as f64))
+                        .map(|v| v as f32)
+                        .map_err(|t| format!("Expected number for point coordinate, got {}", t))
+                };
+                let point = transform.point(coordinate(&values[0])?, coordinate(&values[1])?);
+                let color = color_from_array(&values[2..].to_vec())?;
+                points.push((point, color));
+            }
+            let previous = canvas.draw_color();
+            let mut result = Ok(());
+            for run in points.chunk_by(|a, b| a.1 == b.1) {
+                canvas.set_draw_color(run[0].1);
+                let run: Vec<FPoint> = run.iter().map(|(point, _)| *point).collect();
+                result = canvas
+                    .draw_points(run.as_slice())
+                    .map_err(|e| e.to_string());
+                if result.is_err() {
+                    break;
+                }
+            }
+            canvas.set_draw_color(previous);
+            if result.is_ok() {
+                self.draw_stats.points += points.len() as u64;
+            }
+            result
+        } else {
+            Err("Canvas not initialized".to_string())
+        }
+    }
+
     fn draw_line(&mut self, x1: i32, y1: i32, x2: i32, y2: i32) -> Result<(), String> {
+        let transform = self.draw_transform();
         if let Some(canvas) = &mut self.canvas {
             canvas
-                .draw_line(Point::new(x1, y1), Point::new(x2, y2))
+                .draw_line(
+                    transform.point(x1 as f32, y1 as f32),
+                    transform.point(x2 as f32, y2 as f32),
+                )
                 .map_err(|e| e.to_string())?;
+            self.draw_stats.lines += 1;
             Ok(())
         } else {
             Err("Canvas not initialized".to_string())
         }
     }
 
-    fn present(&mut self) -> Result<(), String> {
+    fn draw_thick_line(
+        &mut self,
+        x1: i32,
+        y1: i32,
+        x2: i32,
+        y2: i32,
+        thickness: i32,
+    ) -> Result<(), String> {
+        if thickness <= 1 {
+            return self.draw_line(x1, y1, x2, y2);
+        }
+        let transform = self.draw_transform();
         if let Some(canvas) = &mut self.canvas {
-            canvas.present();
+            // Thickness is in world units, so it grows with the camera zoom
+            let FPoint { x: x1, y: y1 } = transform.point(x1 as f32, y1 as f32);
+            let FPoint { x: x2, y: y2 } = transform.point(x2 as f32, y2 as f32);
+            let (dx, dy) = (x2 - x1, y2 - y1);
+            let length = (dx * dx + dy * dy).sqrt();
+            let half = thickness as f32 * transform.scale / 2.0;
+            if length == 0.0 {
+                let square = FRect::new(x1 - half, y1 - half, half * 2.0, half * 2.0);
+                canvas.fill_rect(square).map_err(|e| e.to_string())?;
+                self.draw_stats.lines += 1;
+                return Ok(());
+            }
+            // Offset both endpoints along the line's normal to get the quad
+            let (nx, ny) = (-dy / length * half, dx / length * half);
+            scanline_fill(
+                canvas,
+                &[
+                    (x1 + nx, y1 + ny),
+                    (x2 + nx, y2 + ny),
+                    (x2 - nx, y2 - ny),
+                    (x1 - nx, y1 - ny),
+                ],
+            )?;
+            self.draw_stats.lines += 1;
             Ok(())
         } else {
             Err("Canvas not initialized".to_string())
         }
     }
 
-    fn init_event_pump(&mut self) -> Result<(), String> {
-        self.event_pump = Some(self.sdl.event_pump().map_err(|e| e.to_string())?);
+    fn draw_dashed_line(
+        &mut self,
+        x1: i32,
+        y1: i32,
+        x2: i32,
+        y2: i32,
+        dash_len: i32,
+        gap_len: i32,
+    ) -> Result<(), String> {
+        if dash_len <= 0 || gap_len <= 0 {
+            return Err("Dash and gap lengths must be positive".to_string());
+        }
+        let transform = self.draw_transform();
+        if let Some(canvas) = &mut self.canvas {
+            // Dashes are measured in world units and mapped point by point
+            let (x1, y1) = (x1 as f32, y1 as f32);
+            let (dx, dy) = (x2 as f32 - x1, y2 as f32 - y1);
+            let length = (dx * dx + dy * dy).sqrt();
+            if length == 0.0 {
+                canvas
+                    .draw_point(transform.point(x1, y1))
+                    .map_err(|e| e.to_string())?;
+                self.draw_stats.lines += 1;
+                return Ok(());
+            }
+            let (ux, uy) = (dx / length, dy / length);
+            let mut start = 0.0;
+            while start < length {
+                let end = (start + dash_len as f32).min(length);
+                canvas
+                    .draw_line(
+                        transform.point(x1 + ux * start, y1 + uy * start),
+                        transform.point(x1 + ux * end, y1 + uy * end),
+                    )
+                    .map_err(|e| e.to_string())?;
+                start = end + gap_len as f32;
+            }
+            self.draw_stats.lines += 1;
+            Ok(())
+        } else {
+            Err("Canvas not initialized".to_string())
+        }
+    }
+
+    // Spawn particles at (x, y). Config keys, all optional: speed (pixels per
+    // second), angle and spread (degrees, default straight up over a full
+    // circle), lifetime (seconds), gravity (pixels per second squared) and
+    // color ([r, g, b]).
+    fn emit_particles(&mut self, x: f32, y: f32, count: i64, config: &Map) -> Result<(), String> {
+        let speed = map_number(config, "speed", 100.0)? as f32;
+        let angle = map_number(config, "angle", -90.0)? as f32;
+        let spread = map_number(config, "spread", 360.0)? as f32;
+        let lifetime = map_number(config, "lifetime", 1.0)? as f32;
+        let gravity = map_number(config, "gravity", 0.0)? as f32;
+        let color = match config.get("color") {
+            Some(rgb) => {
+                let rgb = rgb
+                    .as_array_ref()
+                    .map_err(|_| "Expected [r, g, b] for color".to_string())?;
+                color_from_array(&rgb)?
+            }
+            None => Color::RGB(255, 255, 255),
+        };
+        if lifetime <= 0.0 {
+            return Err("Particle lifetime must be positive".to_string());
+        }
+
+        for _ in 0..count.max(0) {
+            let direction = (angle + spread * (self.rng.random::<f32>() - 0.5)).to_radians();
+            self.particles.push(Particle {
+                x,
+                y,
+                vx: direction.cos() * speed,
+                vy: direction.sin() * speed,
+                gravity,
+                age: 0.0,
+                lifetime,
+                color,
+            });
+        }
         Ok(())
     }
 
-    fn poll_event(&mut self) -> Result<bool, String> {
-        if let Some(event_pump) = &mut self.event_pump {
-            match event_pump.poll_event() {
-                Some(Event::Quit { .. }) => Ok(false),
-                Some(_) => Ok(true),
-                None => Ok(true),
+    // Advance all particles by dt seconds, drop expired ones and draw the rest
+    fn update_particles(&mut self, dt: f32) -> Result<(), String> {
+        if let Some(canvas) = &mut self.canvas {
+            self.particles.retain_mut(|particle| {
+                particle.age += dt;
+                particle.vy += particle.gravity * dt;
+                particle.x += particle.vx * dt;
+                particle.y += particle.vy * dt;
+                particle.age < particle.lifetime
+            });
+
+            let previous_color = canvas.draw_color();
+            let previous_blend = canvas.blend_mode();
+            canvas.set_blend_mode(BlendMode::Blend);
+            let mut result = Ok(());
+            for particle in &self.particles {
+                let alpha = 255.0 * (1.0 - particle.age / particle.lifetime);
+                let Color { r, g, b, .. } = particle.color;
+                canvas.set_draw_color(Color::RGBA(r, g, b, alpha as u8));
+                result = canvas
+                    .fill_rect(FRect::new(particle.x - 1.0, particle.y - 1.0, 2.0, 2.0))
+                    .map_err(|e| e.to_string());
+                if result.is_err() {
+                    break;
+                }
             }
+            canvas.set_blend_mode(previous_blend);
+            canvas.set_draw_color(previous_color);
+            if result.is_ok() {
+                self.draw_stats.rects += self.particles.len() as u64;
+            }
+            result
         } else {
-            Err("Event pump not initialized".to_string())
+            Err("Canvas not initialized".to_string())
         }
     }
 
-    fn is_key_down(&mut self, key: &str) -> Result<bool, String> {
-        if let Some(event_pump) = &mut self.event_pump {
-            let scancode = match key.to_lowercase().as_str() {
-                "space" => Scancode::Space,
-                "left" => Scancode::Left,
-                "right" => Scancode::Right,
-                "up" => Scancode::Up,
-                "down" => Scancode::Down,
-                _ => return Err(format!("Unsupported key: {}", key)),
-            };
-            let keyboard_state = event_pump.keyboard_state();
-            Ok(keyboard_state.pressed_scancodes().any(|s| s == scancode))
+    // Run a list of draw commands under a single lock. Each command is a map
+    // with an op (fill_rect, draw_rect, draw_line, draw_point or draw_texture),
+    // its coordinates and an optional color that stays set for later commands.
+    fn draw_batch(&mut self, commands: &Array) -> Result<(), String> {
+        for (index, command) in commands.iter().enumerate() {
+            self.draw_batch_command(command)
+                .map_err(|e| format!("draw_batch command {}: {}", index, e))?;
+        }
+        Ok(())
+    }
+
+    fn draw_batch_command(&mut self, command: &Dynamic) -> Result<(), String> {
+        let command = command
+            .read_lock::<Map>()
+            .ok_or_else(|| format!("Expected map, got {}", command.type_name()))?;
+        if let Some(rgb) = command.get("color") {
+            let rgb = rgb
+                .as_array_ref()
+                .map_err(|_| "Expected [r, g, b] for color".to_string())?;
+            let color = color_from_array(&rgb)?;
+            self.set_draw_color(color)?;
+        }
+        let op = command
+            .get("op")
+            .ok_or("Missing op")?
+            .clone()
+            .into_string()
+            .map_err(|t| format!("Expected string for op, got {}", t))?;
+        match op.as_str() {
+            "fill_rect" => self.fill_rect(
+                map_int(&command, "x")?,
+                map_int(&command, "y")?,
+                map_int(&command, "w")?,
+                map_int(&command, "h")?,
+            ),
+            "draw_rect" => self.draw_rect(
+                map_int(&command, "x")?,
+                map_int(&command, "y")?,
+                map_int(&command, "w")?,
+                map_int(&command, "h")?,
+            ),
+            "draw_line" => self.draw_line(
+                map_int(&command, "x1")?,
+                map_int(&command, "y1")?,
+                map_int(&command, "x2")?,
+                map_int(&command, "y2")?,
+            ),
+            "draw_point" => self.draw_point(map_int(&command, "x")?, map_int(&command, "y")?),
+            "draw_texture" => self.draw_texture(
+                map_handle(&command, "texture")?,
+                map_int(&command, "x")?,
+                map_int(&command, "y")?,
+            ),
+            _ => Err(format!("Unknown op: {}", op)),
+        }
+    }
+
+    fn draw_triangle(&mut self, points: [(i32, i32); 3]) -> Result<(), String> {
+        let transform = self.draw_transform();
+        if let Some(canvas) = &mut self.canvas {
+            let outline: Vec<FPoint> = points
+                .iter()
+                .chain(&points[..1])
+                .map(|&(x, y)| transform.point(x as f32, y as f32))
+                .collect();
+            canvas.draw_lines(&outline[..]).map_err(|e| e.to_string())?;
+            self.draw_stats.lines += 1;
+            Ok(())
         } else {
-            Err("Event pump not initialized".to_string())
+            Err("Canvas not initialized".to_string())
         }
     }
 
-    fn is_mouse_button_down(&mut self, button: &str) -> Result<bool, String> {
-        if let Some(event_pump) = &mut self.event_pump {
-            let mouse_state = event_pump.mouse_state();
-            let button_mask = match button.to_lowercase().as_str() {
-                "left" => MouseButton::Left,
-                "right" => MouseButton::Right,
-                "middle" => MouseButton::Middle,
-                _ => return Err(format!("Unsupported mouse button: {}", button)),
-            };
-            Ok(mouse_state.is_mouse_button_pressed(button_mask))
+    fn fill_triangle(&mut self, points: [(i32, i32); 3]) -> Result<(), String> {
+        let transform = self.draw_transform();
+        if let Some(canvas) = &mut self.canvas {
+            let [(x1, y1), (x2, y2), (x3, y3)] = points;
+            let area = (x2 - x1) as i64 * (y3 - y1) as i64 - (x3 - x1) as i64 * (y2 - y1) as i64;
+            if area == 0 {
+                // Collinear points have no interior, so draw the segment they span
+                let path: Vec<FPoint> = points
+                    .iter()
+                    .map(|&(x, y)| transform.point(x as f32, y as f32))
+                    .collect();
+                canvas.draw_lines(&path[..]).map_err(|e| e.to_string())?;
+                self.draw_stats.rects += 1;
+                return Ok(());
+            }
+            let corners = points.map(|(x, y)| {
+                let corner = transform.point(x as f32, y as f32);
+                (corner.x, corner.y)
+            });
+            scanline_fill(canvas, &corners)?;
+            self.draw_stats.rects += 1;
+            Ok(())
         } else {
-            Err("Event pump not initialized".to_string())
+            Err("Canvas not initialized".to_string())
         }
     }
 
-    fn get_mouse_position(&mut self) -> Result<(i64, i64), String> {
-        if let Some(event_pump) = &mut self.event_pump {
-            let mouse_state = event_pump.mouse_state();
-            Ok((mouse_state.x() as i64, mouse_state.y() as i64))
+    // Outline of a circle by the midpoint algorithm, one plotted point per
+    // octant step. The camera moves the center and scales the radius.
+    fn draw_circle(&mut self, x: i32, y: i32, radius: i32) -> Result<(), String> {
+        let transform = self.draw_transform();
+        if let Some(canvas) = &mut self.canvas {
+            let center = transform.point(x as f32, y as f32);
+            let (cx, cy) = (center.x.round() as i32, center.y.round() as i32);
+            let radius = (radius.max(0) as f32 * transform.scale).round() as i32;
+            let mut points = Vec::new();
+            for (dx, dy) in circle_octant(radius) {
+                for (px, py) in [
+                    (dx, dy),
+                    (dy, dx),
+                    (-dy, dx),
+                    (-dx, dy),
+                    (-dx, -dy),
+                    (-dy, -dx),
+                    (dy, -dx),
+                    (dx, -dy),
+                ] {
+                    points.push(FPoint::new((cx + px) as f32, (cy + py) as f32));
+                }
+            }
+            canvas
+                .draw_points(points.as_slice())
+                .map_err(|e| e.to_string())?;
+            self.draw_stats.points += points.len() as u64;
+            Ok(())
         } else {
-            Err("Event pump not initialized".to_string())
+            Err("Canvas not initialized".to_string())
         }
     }
 
-    fn delay(&self, ms: u32) -> Result<(), String> {
-        std::thread::sleep(Duration::from_millis(ms as u64));
-        Ok(())
+    // Filled circle as one horizontal span per row, covering the same
+    // pixels as draw_circle's outline and everything inside it
+    fn fill_circle(&mut self, x: i32, y: i32, radius: i32) -> Result<(), String> {
+        let transform = self.draw_transform();
+        if let Some(canvas) = &mut self.canvas {
+            let center = transform.point(x as f32, y as f32);
+            let (cx, cy) = (center.x.round() as i32, center.y.round() as i32);
+            let radius = (radius.max(0) as f32 * transform.scale).round() as i32;
+            // Widest span of each row offset from the center
+            let mut half_widths = vec![0; radius as usize + 1];
+            for (dx, dy) in circle_octant(radius) {
+                half_widths[dy as usize] = half_widths[dy as usize].max(dx);
+                half_widths[dx as usize] = half_widths[dx as usize].max(dy);
+            }
+            let mut spans = Vec::with_capacity(half_widths.len() * 2);
+            for (dy, &half) in half_widths.iter().enumerate() {
+                let dy = dy as i32;
+                let rows = if dy == 0 {
+                    vec![cy]
+                } else {
+                    vec![cy - dy, cy + dy]
+                };
+                for row in rows {
+                    spans.push(FRect::new(
+                        (cx - half) as f32,
+                        row as f32,
+                        (2 * half + 1) as f32,
+                        1.0,
+                    ));
+                }
+            }
+            canvas.fill_rects(&spans).map_err(|e| e.to_string())?;
+            self.draw_stats.rects += spans.len() as u64;
+            Ok(())
+        } else {
+            Err("Canvas not initialized".to_string())
+        }
     }
-}
 
-// Rhai module to register SDL functions
-pub fn register_sdl_module(engine: &mut Engine, sdl_context: Arc<Mutex<SDLContext>>) {
-    let sdl_context_clone = sdl_context.clone();
-    engine.register_fn(
-        "create_window",
-        move |title: &str, width: i64, height: i64| -> Result<(), Box<EvalAltResult>> {
-            sdl_context_clone
-                .lock()
-                .map_err(|e| {
-                    Box::new(EvalAltResult::ErrorRuntime(
-                        Dynamic::from(e.to_string()),
-                        Default::default(),
+    fn draw_bezier(&mut self, points: &[(f32, f32)], segments: i32) -> Result<(), String> {
+        let transform = self.draw_transform();
+        if let Some(canvas) = &mut self.canvas {
+            let first = points[0];
+            let last = points[points.len() - 1];
+            if segments <= 1 {
+                canvas
+                    .draw_line(
+                        transform.point(first.0, first.1),
+                        transform.point(last.0, last.1),
+                    )
+                    .map_err(|e| e.to_string())?;
+                self.draw_stats.lines += 1;
+                return Ok(());
+            }
+            let curve: Vec<FPoint> = (0..=segments)
+                .map(|i| {
+                    let (x, y) = bezier_point(points, i as f32 / segments as f32);
+                    transform.point(x, y)
+                })
+                .collect();
+            canvas.draw_lines(&curve[..]).map_err(|e| e.to_string())?;
+            self.draw_stats.lines += 1;
+            Ok(())
+        } else {
+            Err("Canvas not initialized".to_string())
+        }
+    }
+
+    // Any format SDL_image understands, such as PNG or JPG
+    fn load_texture(&mut self, path: &str) -> Result<i64, String> {
+        if let Some(texture_creator) = &self.texture_creator {
+            let texture = texture_creator
+                .load_texture(path)
+                .map_err(|e| format!("Failed to load {}: {}", path, e))?;
+            let id = self.next_texture_id;
+            self.next_texture_id += 1;
+            self.textures.insert(id, texture);
+            Ok(id)
+        } else {
+            Err("Canvas not initialized".to_string())
+        }
+    }
+
+    fn load_bmp_texture(&mut self, path: &str) -> Result<i64, String> {
+        if let Some(canvas) = &mut self.canvas {
+            let surface =
+                Surface::load_bmp(path).map_err(|e| format!("Failed to load {}: {}", path, e))?;
+            let texture = canvas
+                .create_texture_from_surface(&surface)
+                .map_err(|e| e.to_string())?;
+            let id = self.next_texture_id;
+            self.next_texture_id += 1;
+            self.textures.insert(id, texture);
+            Ok(id)
+        } else {
+            Err("Canvas not initialized".to_string())
+        }
+    }
+
+    // Decode an image file on a background thread and return its handle right
+    // away. The texture is created on this thread once decoding finishes,
+    // checked at each present and by is_texture_ready; until then the handle
+    // cannot be drawn.
+    fn load_texture_async(&mut self, path: &str) -> Result<i64, String> {
+        let format = pixel_format_from_name("RGBA32")?;
+        let path = path.to_string();
+        let loader = std::thread::spawn(move || -> Result<DecodedImage, String> {
+            let surface = Surface::from_file(&path)
+                .and_then(|surface| surface.convert_format(format))
+                .map_err(|e| format!("Failed to load {}: {}", path, e))?;
+            Ok(DecodedImage {
+                pixels: surface.with_lock(|pixels| pixels.to_vec()),
+                width: surface.width(),
+                height: surface.height(),
+                pitch: surface.pitch(),
+            })
+        });
+        let id = self.next_texture_id;
+        self.next_texture_id += 1;
+        self.pending_textures.insert(id, loader);
+        Ok(id)
+    }
+
+    // Turn finished background decodes into textures. Failures are kept until
+    // is_texture_ready reports them for their handle.
+    fn finish_texture_loads(&mut self) {
+        let Some(canvas) = &self.canvas else {
+            return;
+        };
+        let finished: Vec<i64> = self
+            .pending_textures
+            .iter()
+            .filter(|(_, loader)| loader.is_finished())
+            .map(|(id, _)| *id)
+            .collect();
+        for id in finished {
+            let loader = self.pending_textures.remove(&id).unwrap();
+            let texture = loader
+                .join()
+                .map_err(|_| "Texture loader thread panicked".to_string())
+                .and_then(|decoded| {
+                    let mut image = decoded?;
+                    let format = pixel_format_from_name("RGBA32")?;
+                    let surface = Surface::from_data(
+                        &mut image.pixels,
+                        image.width,
+                        image.height,
+                        image.pitch,
+                        format,
+                    )
+                    .map_err(|e| e.to_string())?;
+                    canvas
+                        .create_texture_from_surface(&surface)
+                        .map_err(|e| e.to_string())
+                });
+            match texture {
+                Ok(texture) => {
+                    self.textures.insert(id, texture);
+                }
+                Err(e) => {
+                    self.texture_load_errors.insert(id, e);
+                }
+            }
+        }
+    }
+
+    fn is_texture_ready(&mut self, id: i64) -> Result<bool, String> {
+        self.finish_texture_loads();
+        if let Some(e) = self.texture_load_errors.remove(&id) {
+            Err(e)
+        } else if self.textures.contains_key(&id) {
+            Ok(true)
+        } else if self.pending_textures.contains_key(&id) {
+            Ok(false)
+        } else {
+            Err(format!("Invalid texture handle: {}", id))
+        }
+    }
+
+    fn draw_texture(&mut self, id: i64, x: i32, y: i32) -> Result<(), String> {
+        let transform = self.draw_transform();
+        if let Some(canvas) = &mut self.canvas {
+            let texture = self
+                .textures
+                .get(&id)
+                .ok_or_else(|| format!("Invalid texture handle: {}", id))?;
+            let dst = FRect::new(
+                x as f32,
+                y as f32,
+                texture.width() as f32,
+                texture.height() as f32,
+            );
+            canvas
+                .copy(texture, None, transform.rect(dst))
+                .map_err(|e| e.to_string())?;
+            self.draw_stats.textures += 1;
+            Ok(())
+        } else {
+            Err("Canvas not initialized".to_string())
+        }
+    }
+
+    fn draw_texture_scaled(
+        &mut self,
+        id: i64,
+        x: i32,
+        y: i32,
+        w: i32,
+        h: i32,
+    ) -> Result<(), String> {
+        let transform = self.draw_transform();
+        if let Some(canvas) = &mut self.canvas {
+            let texture = self
+                .textures
+                .get(&id)
+                .ok_or_else(|| format!("Invalid texture handle: {}", id))?;
+            let dst = FRect::new(x as f32, y as f32, w as f32, h as f32);
+            canvas
+                .copy(texture, None, transform.rect(dst))
+                .map_err(|e| e.to_string())?;
+            self.draw_stats.textures += 1;
+            Ok(())
+        } else {
+            Err("Canvas not initialized".to_string())
+        }
+    }
+
+    // Scale a texture to the largest size that fits the box without changing
+    // its aspect ratio, centered so the leftover space is split evenly
+    fn draw_texture_fit(&mut self, id: i64, x: i32, y: i32, w: i32, h: i32) -> Result<(), String> {
+        let transform = self.draw_transform();
+        if let Some(canvas) = &mut self.canvas {
+            let texture = self
+                .textures
+                .get(&id)
+                .ok_or_else(|| format!("Invalid texture handle: {}", id))?;
+            let (tw, th) = (texture.width() as f32, texture.height() as f32);
+            let scale = (w as f32 / tw).min(h as f32 / th).max(0.0);
+            let (fw, fh) = (tw * scale, th * scale);
+            let dst = FRect::new(
+                x as f32 + (w as f32 - fw) / 2.0,
+                y as f32 + (h as f32 - fh) / 2.0,
+                fw,
+                fh,
+            );
+            canvas
+                .copy(texture, None, transform.rect(dst))
+                .map_err(|e| e.to_string())?;
+            self.draw_stats.textures += 1;
+            Ok(())
+        } else {
+            Err("Canvas not initialized".to_string())
+        }
+    }
+
+    // Draw sprite maps #{ texture, x, y, w, h, z } back to front. The sort is
+    // stable, so sprites with equal z keep their submission order.
+    fn draw_sorted(&mut self, sprites: &Array) -> Result<(), String> {
+        let mut parsed = Vec::with_capacity(sprites.len());
+        for (index, sprite) in sprites.iter().enumerate() {
+            let sprite = sprite
+                .read_lock::<Map>()
+                .ok_or_else(|| format!("draw_sorted sprite {}: Expected map", index))?;
+            let parse = || -> Result<(f64, i64, FRect), String> {
+                let dst = FRect::new(
+                    map_int(&sprite, "x")? as f32,
+                    map_int(&sprite, "y")? as f32,
+                    map_int(&sprite, "w")? as f32,
+                    map_int(&sprite, "h")? as f32,
+                );
+                Ok((
+                    map_number(&sprite, "z", 0.0)?,
+                    map_handle(&sprite, "texture")?,
+                    dst,
+                ))
+            };
+            parsed.push(parse().map_err(|e| format!("draw_sorted sprite {}: {}", index, e))?);
+        }
+        parsed.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        let transform = self.draw_transform();
+        if let Some(canvas) = &mut self.canvas {
+            for (_, id, dst) in parsed {
+                let texture = self
+                    .textures
+                    .get(&id)
+                    .ok_or_else(|| format!("Invalid texture handle: {}", id))?;
+                canvas
+                    .copy(texture, None, transform.rect(dst))
+                    .map_err(|e| e.to_string())?;
+                self.draw_stats.textures += 1;
+            }
+            Ok(())
+        } else {
+            Err("Canvas not initialized".to_string())
+        }
+    }
+
+    // Draw many regions of one texture, such as tiles or bullets from an
+    // atlas. Each instance is #{ sx, sy, sw, sh, dx, dy, dw, dh }: the source
+    // rect in the texture and the destination rect on screen.
+    fn draw_sprite_batch(&mut self, id: i64, instances: &Array) -> Result<(), String> {
+        let transform = self.draw_transform();
+        if let Some(canvas) = &mut self.canvas {
+            let texture = self
+                .textures
+                .get(&id)
+                .ok_or_else(|| format!("Invalid texture handle: {}", id))?;
+            for (index, instance) in instances.iter().enumerate() {
+                let instance = instance
+                    .read_lock::<Map>()
+                    .ok_or_else(|| format!("draw_sprite_batch instance {}: Expected map", index))?;
+                let rects = || -> Result<(FRect, FRect), String> {
+                    let rect = |x: &str, y: &str, w: &str, h: &str| -> Result<FRect, String> {
+                        Ok(FRect::new(
+                            map_int(&instance, x)? as f32,
+                            map_int(&instance, y)? as f32,
+                            map_int(&instance, w)? as f32,
+                            map_int(&instance, h)? as f32,
+                        ))
+                    };
+                    Ok((rect("sx", "sy", "sw", "sh")?, rect("dx", "dy", "dw", "dh")?))
+                };
+                let (src, dst) =
+                    rects().map_err(|e| format!("draw_sprite_batch instance {}: {}", index, e))?;
+                canvas
+                    .copy(texture, src, transform.rect(dst))
+                    .map_err(|e| e.to_string())?;
+            }
+            self.draw_stats.textures += instances.len() as u64;
+            Ok(())
+        } else {
+            Err("Canvas not initialized".to_string())
+        }
+    }
+
+    // Triangles from vertex maps #{ x, y, r, g, b, a, u, v }. Only x and y are
+    // required; color channels are 0-255 and default to 255, u and v are the
+    // normalized texture coordinates and default to 0. Each three indices form
+    // a triangle; with no indices every three vertices do. texture is -1 for
+    // plain colored geometry, in which case u and v are ignored.
+    fn draw_geometry(&mut self, id: i64, vertices: &Array, indices: &Array) -> Result<(), String> {
+        let transform = self.draw_transform();
+        let mut parsed = Vec::with_capacity(vertices.len());
+        for (index, vertex) in vertices.iter().enumerate() {
+            let vertex = vertex
+                .read_lock::<Map>()
+                .ok_or_else(|| format!("draw_geometry vertex {}: Expected map", index))?;
+            let parse = || -> Result<SDL_Vertex, String> {
+                let number = |key: &str| -> Result<f32, String> {
+                    if !vertex.contains_key(key) {
+                        return Err(format!("Missing {}", key));
+                    }
+                    Ok(map_number(&vertex, key, 0.0)? as f32)
+                };
+                let channel = |key: &str, default: f64| -> Result<f32, String> {
+                    Ok((map_number(&vertex, key, default)? / 255.0).clamp(0.0, 1.0) as f32)
+                };
+                let position = transform.point(number("x")?, number("y")?);
+                Ok(SDL_Vertex {
+                    position: SDL_FPoint {
+                        x: position.x,
+                        y: position.y,
+                    },
+                    color: SDL_FColor {
+                        r: channel("r", 255.0)?,
+                        g: channel("g", 255.0)?,
+                        b: channel("b", 255.0)?,
+                        a: channel("a", 255.0)?,
+                    },
+                    tex_coord: SDL_FPoint {
+                        x: map_number(&vertex, "u", 0.0)? as f32,
+                        y: map_number(&vertex, "v", 0.0)? as f32,
+                    },
+                })
+            };
+            parsed.push(parse().map_err(|e| format!("draw_geometry vertex {}: {}", index, e))?);
+        }
+        let mut parsed_indices = Vec::with_capacity(indices.len());
+        for value in indices {
+            let index = value
+                .as_int()
+                .map_err(|t| format!("Expected integer vertex index, got {}", t))?;
+            if index < 0 || index as usize >= parsed.len() {
+                return Err(format!(
+                    "Vertex index {} out of range for {} vertices",
+                    index,
+                    parsed.len()
+                ));
+            }
+            parsed_indices.push(index as i32);
+        }
+        let count = if indices.is_empty() {
+            parsed.len()
+        } else {
+            parsed_indices.len()
+        };
+        if !count.is_multiple_of(3) {
+            return Err(format!("Expected whole triangles, got {} corners", count));
+        }
+
+        if let Some(canvas) = &mut self.canvas {
+            let texture = if id == -1 {
+                std::ptr::null_mut()
+            } else {
+                self.textures
+                    .get(&id)
+                    .ok_or_else(|| format!("Invalid texture handle: {}", id))?
+                    .raw()
+            };
+            let (index_ptr, index_count) = if parsed_indices.is_empty() {
+                (std::ptr::null(), 0)
+            } else {
+                (parsed_indices.as_ptr(), parsed_indices.len() as i32)
+            };
+            let drawn = unsafe {
+                SDL_RenderGeometry(
+                    canvas.raw(),
+                    texture,
+                    parsed.as_ptr(),
+                    parsed.len() as i32,
+                    index_ptr,
+                    index_count,
+                )
+            };
+            if drawn {
+                self.draw_stats.rects += (count / 3) as u64;
+                Ok(())
+            } else {
+                Err(sdl3::get_error().to_string())
+            }
+        } else {
+            Err("Canvas not initialized".to_string())
+        }
+    }
+
+    // Draw a texture stretched over a dest rect with its border pixels kept at
+    // their size: corners are copied as-is, edges stretch along one axis and
+    // the center along both. border is in texture pixels, and is shrunk to fit
+    // when the texture or dest is smaller than two borders.
+    fn draw_nine_patch(
+        &mut self,
+        id: i64,
+        dx: i32,
+        dy: i32,
+        dw: i32,
+        dh: i32,
+        border: i32,
+    ) -> Result<(), String> {
+        let transform = self.draw_transform();
+        if let Some(canvas) = &mut self.canvas {
+            let texture = self
+                .textures
+                .get(&id)
+                .ok_or_else(|| format!("Invalid texture handle: {}", id))?;
+            let (sw, sh) = (texture.width() as f32, texture.height() as f32);
+            let (dw, dh) = (dw.max(0) as f32, dh.max(0) as f32);
+            let border = (border.max(0) as f32)
+                .min(sw / 2.0)
+                .min(sh / 2.0)
+                .min(dw / 2.0)
+                .min(dh / 2.0);
+            // Column and row edges, as (offset, size) pairs in source and dest
+            let spans = |source: f32, dest: f32| {
+                [
+                    ((0.0, border), (0.0, border)),
+                    (
+                        (border, source - 2.0 * border),
+                        (border, dest - 2.0 * border),
+                    ),
+                    ((source - border, border), (dest - border, border)),
+                ]
+            };
+            for ((src_x, src_w), (dst_x, dst_w)) in spans(sw, dw) {
+                for ((src_y, src_h), (dst_y, dst_h)) in spans(sh, dh) {
+                    if src_w <= 0.0 || src_h <= 0.0 || dst_w <= 0.0 || dst_h <= 0.0 {
+                        continue;
+                    }
+                    let src = FRect::new(src_x, src_y, src_w, src_h);
+                    let dst = FRect::new(dx as f32 + dst_x, dy as f32 + dst_y, dst_w, dst_h);
+                    canvas
+                        .copy(texture, src, transform.rect(dst))
+                        .map_err(|e| e.to_string())?;
+                }
+            }
+            self.draw_stats.textures += 1;
+            Ok(())
+        } else {
+            Err("Canvas not initialized".to_string())
+        }
+    }
+
+    // Texture whose pixels the script rewrites with update_texture. format is a
+    // pixel layout name such as RGBA32, RGBA8888 or RGB24, see pixel_format_from_name.
+    fn create_streaming_texture(&mut self, w: u32, h: u32, format: &str) -> Result<i64, String> {
+        let format = pixel_format_from_name(format)?;
+        if let Some(canvas) = &mut self.canvas {
+            let texture = canvas
+                .texture_creator()
+                .create_texture_streaming(format, w, h)
+                .map_err(|e| e.to_string())?;
+            let id = self.next_texture_id;
+            self.next_texture_id += 1;
+            self.textures.insert(id, texture);
+            Ok(id)
+        } else {
+            Err("Canvas not initialized".to_string())
+        }
+    }
+
+    // Replace a streaming texture's pixels with tightly packed rows in its format
+    fn update_texture(&mut self, id: i64, pixels: &[u8]) -> Result<(), String> {
+        let texture = self
+            .textures
+            .get_mut(&id)
+            .ok_or_else(|| format!("Invalid texture handle: {}", id))?;
+        let pitch = texture.width() as usize * texture.format().byte_size_per_pixel();
+        let expected = pitch * texture.height() as usize;
+        if pixels.len() != expected {
+            return Err(format!(
+                "Expected {} bytes of pixel data, got {}",
+                expected,
+                pixels.len()
+            ));
+        }
+        texture
+            .update(None, pixels, pitch)
+            .map_err(|e| e.to_string())
+    }
+
+    // Draw a row-major image of packed 0xRRGGBB ints, width pixels per row,
+    // with its top left corner at (x, y). The pixels are uploaded in one go
+    // to a streaming texture instead of drawn point by point.
+    fn plot_pixels(&mut self, x: i32, y: i32, width: i64, data: &Array) -> Result<(), String> {
+        if width <= 0 || data.is_empty() || !data.len().is_multiple_of(width as usize) {
+            return Err(format!(
+                "Pixel data length {} is not a non-zero multiple of width {}",
+                data.len(),
+                width
+            ));
+        }
+        let (w, h) = (width as u32, (data.len() / width as usize) as u32);
+        let mut pixels = Vec::with_capacity(data.len() * 4);
+        for value in data {
+            let rgb = value
+                .as_int()
+                .map_err(|t| format!("Expected integer pixel, got {}", t))?;
+            // XRGB8888 is a native-endian 32-bit value, like the packed int
+            pixels.extend_from_slice(&(rgb as u32 & 0xFF_FFFF).to_ne_bytes());
+        }
+        let transform = self.draw_transform();
+        if let Some(canvas) = &mut self.canvas {
+            if let Some(texture) = self
+                .pixel_plot
+                .take_if(|texture| texture.width() != w || texture.height() != h)
+            {
+                // Safe: the canvas that owns the texture is still alive
+                unsafe { texture.destroy() };
+            }
+            if self.pixel_plot.is_none() {
+                let texture = canvas
+                    .texture_creator()
+                    .create_texture_streaming(pixel_format_from_name("XRGB8888")?, w, h)
+                    .map_err(|e| e.to_string())?;
+                self.pixel_plot = Some(texture);
+            }
+            let texture = self.pixel_plot.as_mut().unwrap();
+            texture
+                .update(None, &pixels, w as usize * 4)
+                .map_err(|e| e.to_string())?;
+            let dst = FRect::new(x as f32, y as f32, w as f32, h as f32);
+            canvas
+                .copy(texture, None, transform.rect(dst))
+                .map_err(|e| e.to_string())?;
+            self.draw_stats.textures += 1;
+            Ok(())
+        } else {
+            Err("Canvas not initialized".to_string())
+        }
+    }
+
+    // Blank texture that blit_texture_to_texture can draw into
+    fn create_target_texture(&mut self, w: u32, h: u32) -> Result<i64, String> {
+        if let Some(canvas) = &mut self.canvas {
+            let texture = blank_target_texture(canvas, w, h)?;
+            let id = self.next_texture_id;
+            self.next_texture_id += 1;
+            self.textures.insert(id, texture);
+            Ok(id)
+        } else {
+            Err("Canvas not initialized".to_string())
+        }
+    }
+
+    // Copy src_rect of texture src into dst_rect of texture dst, stretching
+    // to fit. dst must be a render target such as one from
+    // create_target_texture. Both rects are [x, y, w, h] within their texture.
+    fn blit_texture_to_texture(
+        &mut self,
+        src: i64,
+        dst: i64,
+        src_rect: &Dynamic,
+        dst_rect: &Dynamic,
+    ) -> Result<(), String> {
+        let Some(canvas) = &mut self.canvas else {
+            return Err("Canvas not initialized".to_string());
+        };
+        let texture = |id: i64| {
+            self.textures
+                .get(&id)
+                .ok_or_else(|| format!("Invalid texture handle: {}", id))
+        };
+        let (src_texture, dst_texture) = (texture(src)?, texture(dst)?);
+        let inside = |rect: Rect, texture: &Texture| {
+            rect.x() >= 0
+                && rect.y() >= 0
+                && rect.width() > 0
+                && rect.height() > 0
+                && rect.right() as u32 <= texture.width()
+                && rect.bottom() as u32 <= texture.height()
+        };
+        let src_rect = rect_from_dynamic(src_rect, "src_rect")?;
+        if !inside(src_rect, src_texture) {
+            return Err("src_rect must lie within the source texture".to_string());
+        }
+        let dst_rect = rect_from_dynamic(dst_rect, "dst_rect")?;
+        if !inside(dst_rect, dst_texture) {
+            return Err("dst_rect must lie within the destination texture".to_string());
+        }
+        let renderer = canvas.raw();
+        let previous_target = unsafe { SDL_GetRenderTarget(renderer) };
+        if !unsafe { SDL_SetRenderTarget(renderer, dst_texture.raw()) } {
+            return Err(sdl3::get_error().to_string());
+        }
+        let result = canvas
+            .copy(src_texture, FRect::from(src_rect), FRect::from(dst_rect))
+            .map_err(|e| e.to_string());
+        unsafe { SDL_SetRenderTarget(renderer, previous_target) };
+        result
+    }
+
+    // Transparent render target that draws can be routed to with
+    // set_active_layer. Handle 0 is reserved for the screen. A layer created at
+    // the window's pixel size follows it when the window is resized.
+    fn create_layer(&mut self, w: u32, h: u32) -> Result<i64, String> {
+        if let Some(canvas) = &mut self.canvas {
+            let texture = blank_target_texture(canvas, w, h)?;
+            let id = self.next_layer_id;
+            self.next_layer_id += 1;
+            self.layers.insert(id, texture);
+            if canvas.output_size().ok() == Some((w, h)) {
+                self.window_sized_layers.insert(id);
+            }
+            Ok(id)
+        } else {
+            Err("Canvas not initialized".to_string())
+        }
+    }
+
+    // Recreate a layer at a new size. The contents are cleared to transparent,
+    // so redraw the layer afterwards. A layer resized to the window's pixel
+    // size follows the window from then on, any other size stops following it.
+    fn resize_layer(&mut self, handle: i64, w: u32, h: u32) -> Result<(), String> {
+        if let Some(canvas) = &mut self.canvas {
+            if !self.layers.contains_key(&handle) {
+                return Err(format!("Invalid layer handle: {}", handle));
+            }
+            let texture = blank_target_texture(canvas, w, h)?;
+            if self.active_layer == handle
+                && !unsafe { SDL_SetRenderTarget(canvas.raw(), texture.raw()) }
+            {
+                unsafe { texture.destroy() };
+                return Err(sdl3::get_error().to_string());
+            }
+            if canvas.output_size().ok() == Some((w, h)) {
+                self.window_sized_layers.insert(handle);
+            } else {
+                self.window_sized_layers.remove(&handle);
+            }
+            if let Some(previous) = self.layers.insert(handle, texture) {
+                // Safe: the canvas that owns the texture is still alive
+                unsafe { previous.destroy() };
+            }
+            Ok(())
+        } else {
+            Err("Canvas not initialized".to_string())
+        }
+    }
+
+    fn set_active_layer(&mut self, handle: i64) -> Result<(), String> {
+        if let Some(canvas) = &mut self.canvas {
+            let target = if handle == 0 {
+                std::ptr::null_mut()
+            } else {
+                self.layers
+                    .get(&handle)
+                    .ok_or_else(|| format!("Invalid layer handle: {}", handle))?
+                    .raw()
+            };
+            if !unsafe { SDL_SetRenderTarget(canvas.raw(), target) } {
+                return Err(sdl3::get_error().to_string());
+            }
+            self.active_layer = handle;
+            Ok(())
+        } else {
+            Err("Canvas not initialized".to_string())
+        }
+    }
+
+    // Blit layers onto the screen in the given order, each stretched over the
+    // whole output with its opacity (0.0-1.0) applied as alpha modulation.
+    // The active layer is restored afterwards, even when a layer fails.
+    fn composite_layers(&mut self, order: Array, opacities: Array) -> Result<(), String> {
+        let active_layer = self.active_layer;
+        self.set_active_layer(0)?;
+        let result = self.blit_layers(&order, &opacities);
+        let restored = self.set_active_layer(active_layer);
+        result.and(restored)
+    }
+
+    // The drawing half of composite_layers, onto whatever target is set
+    fn blit_layers(&mut self, order: &Array, opacities: &Array) -> Result<(), String> {
+        let Some(canvas) = &mut self.canvas else {
+            return Err("Canvas not initialized".to_string());
+        };
+        for (i, handle) in order.iter().enumerate() {
+            let handle = handle
+                .as_int()
+                .map_err(|_| "Layer handles must be integers".to_string())?;
+            if handle == 0 {
+                continue;
+            }
+            let opacity = match opacities.get(i) {
+                Some(value) => value
+                    .as_float()
+                    .or_else(|_| value.as_int().map(|v| v as f64))
+                    .map_err(|t| format!("Expected number for opacity, got {}", t))?,
+                None => 1.0,
+            };
+            let layer = self
+                .layers
+                .get_mut(&handle)
+                .ok_or_else(|| format!("Invalid layer handle: {}", handle))?;
+            layer.set_alpha_mod((opacity.clamp(0.0, 1.0) * 255.0).round() as u8);
+            let result = canvas.copy(layer, None, None).map_err(|e| e.to_string());
+            layer.set_alpha_mod(255);
+            result?;
+            self.draw_stats.textures += 1;
+        }
+        Ok(())
+    }
+
+    fn load_font(&mut self, path: &str, point_size: f32) -> Result<i64, String> {
+        if self.ttf.is_none() {
+            let ttf = sdl3::ttf::init().map_err(|e| e.to_string())?;
+            self.ttf = Some(Box::leak(Box::new(ttf)));
+        }
+        // Each file is read once and shared by every size loaded from it. The
+        // bytes live as long as the leaked TTF context, like the fonts using them.
+        let bytes = match self.font_files.get(path) {
+            Some(bytes) => *bytes,
+            None => {
+                let bytes: &'static [u8] = Box::leak(
+                    fs::read(path)
+                        .map_err(|e| format!("Failed to load font {}: {}", path, e))?
+                        .into_boxed_slice(),
+                );
+                self.font_files.insert(path.to_string(), bytes);
+                bytes
+            }
+        };
+        let stream = IOStream::from_bytes(bytes).map_err(|e| e.to_string())?;
+        let font = self
+            .ttf
+            .unwrap()
+            .load_font_from_iostream(stream, point_size)
+            .map_err(|e| format!("Failed to load font {}: {}", path, e))?;
+        let id = self.next_font_id;
+        self.next_font_id += 1;
+        self.fonts.insert(id, font);
+        Ok(id)
+    }
+
+    fn draw_text(&mut self, font: i64, text: &str, x: i32, y: i32) -> Result<(), String> {
+        if let Some(canvas) = &self.canvas {
+            let color = canvas.draw_color();
+            self.draw_text_colored(font, text, x, y, color)
+        } else {
+            Err("Canvas not initialized".to_string())
+        }
+    }
+
+    fn measure_text(&self, font: i64, text: &str) -> Result<(u32, u32), String> {
+        self.fonts
+            .get(&font)
+            .ok_or_else(|| format!("Invalid font handle: {}", font))?
+            .size_of(text)
+            .map_err(|e| e.to_string())
+    }
+
+    fn draw_text_colored(
+        &mut self,
+        font: i64,
+        text: &str,
+        x: i32,
+        y: i32,
+        color: Color,
+    ) -> Result<(), String> {
+        self.draw_text_rotated_colored(font, text, x, y, 0.0, color)
+    }
+
+    // Text at (x, y) turned clockwise by angle degrees around its own center
+    fn draw_text_rotated(
+        &mut self,
+        font: i64,
+        text: &str,
+        x: i32,
+        y: i32,
+        angle: f64,
+    ) -> Result<(), String> {
+        if let Some(canvas) = &self.canvas {
+            let color = canvas.draw_color();
+            self.draw_text_rotated_colored(font, text, x, y, angle, color)
+        } else {
+            Err("Canvas not initialized".to_string())
+        }
+    }
+
+    fn draw_text_rotated_colored(
+        &mut self,
+        font_id: i64,
+        text: &str,
+        x: i32,
+        y: i32,
+        angle: f64,
+        color: Color,
+    ) -> Result<(), String> {
+        if let Some(canvas) = &mut self.canvas {
+            let font = self
+                .fonts
+                .get(&font_id)
+                .ok_or_else(|| format!("Invalid font handle: {}", font_id))?;
+            // TTF refuses to render zero-width text
+            if text.is_empty() {
+                return Ok(());
+            }
+            let key = (
+                font_id,
+                text.to_string(),
+                [color.r, color.g, color.b, color.a],
+            );
+            if !self.text_cache.contains_key(&key) {
+                let surface = font
+                    .render(text)
+                    .blended(color)
+                    .map_err(|e| e.to_string())?;
+                let texture = canvas
+                    .create_texture_from_surface(&surface)
+                    .map_err(|e| e.to_string())?;
+                if self.text_cache.len() >= TEXT_CACHE_LIMIT {
+                    for (_, stale) in self.text_cache.drain() {
+                        // Safe: the canvas that owns the texture is still alive
+                        unsafe { stale.destroy() };
+                    }
+                }
+                self.text_cache.insert(key.clone(), texture);
+            }
+            let texture = &self.text_cache[&key];
+            let dst = FRect::new(
+                x as f32,
+                y as f32,
+                texture.width() as f32,
+                texture.height() as f32,
+            );
+            // A None center rotates around the middle of dst
+            canvas
+                .copy_ex(texture, None, dst, angle, None, false, false)
+                .map_err(|e| e.to_string())?;
+            self.draw_stats.text += 1;
+            Ok(())
+        } else {
+            Err("Canvas not initialized".to_string())
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn draw_text_bg(
+        &mut self,
+        font: i64,
+        text: &str,
+        x: i32,
+        y: i32,
+        text_rgb: &Array,
+        bg_rgb: &Array,
+        padding: i32,
+    ) -> Result<(), String> {
+        // A negative padding would shift the box off the text
+        let padding = padding.max(0);
+        let text_color = color_from_array(text_rgb)?;
+        let bg_color = color_from_array(bg_rgb)?;
+        let (w, h) = self.measure_text(font, text)?;
+        if let Some(canvas) = &mut self.canvas {
+            let previous = canvas.draw_color();
+            canvas.set_draw_color(bg_color);
+            let background = Rect::new(
+                x - padding,
+                y - padding,
+                w + 2 * padding as u32,
+                h + 2 * padding as u32,
+            );
+            let result = canvas.fill_rect(background).map_err(|e| e.to_string());
+            canvas.set_draw_color(previous);
+            result?;
+            self.draw_stats.rects += 1;
+        } else {
+            return Err("Canvas not initialized".to_string());
+        }
+        self.draw_text_colored(font, text, x, y, text_color)
+    }
+
+    // Text with a one pixel outline, drawn as copies in the outline color
+    // offset in the 8 directions underneath the main text
+    fn draw_text_outlined(
+        &mut self,
+        font: i64,
+        text: &str,
+        x: i32,
+        y: i32,
+        text_rgb: &Array,
+        outline_rgb: &Array,
+    ) -> Result<(), String> {
+        let text_color = color_from_array(text_rgb)?;
+        let outline_color = color_from_array(outline_rgb)?;
+        for (dx, dy) in [
+            (-1, -1),
+            (0, -1),
+            (1, -1),
+            (-1, 0),
+            (1, 0),
+            (-1, 1),
+            (0, 1),
+            (1, 1),
+        ] {
+            self.draw_text_colored(font, text, x + dx, y + dy, outline_color)?;
+        }
+        self.draw_text_colored(font, text, x, y, text_color)
+    }
+
+    // Text in the current draw color with each glyph drawn separately, scaled
+    // by scale and spaced letter_spacing pixels further apart than the font's
+    // own advance (negative spacing tightens). Glyph textures are cached.
+    fn draw_text_styled(
+        &mut self,
+        font: i64,
+        text: &str,
+        x: i32,
+        y: i32,
+        scale: f32,
+        letter_spacing: f32,
+    ) -> Result<(), String> {
+        if let Some(canvas) = &mut self.canvas {
+            let ttf_font = self
+                .fonts
+                .get(&font)
+                .ok_or_else(|| format!("Invalid font handle: {}", font))?;
+            let color = canvas.draw_color();
+            let key_color = [color.r, color.g, color.b, color.a];
+            let mut pen_x = x as f32;
+            for ch in text.chars() {
+                let advance = ttf_font
+                    .find_glyph_metrics(ch)
+                    .map_or(0, |metrics| metrics.advance) as f32;
+                let key = (font, ch, key_color);
+                if !self.glyph_cache.contains_key(&key)
+                    && let Ok(surface) = ttf_font.render_char(ch).blended(color)
+                {
+                    let texture = canvas
+                        .create_texture_from_surface(&surface)
+                        .map_err(|e| e.to_string())?;
+                    // Fading or animated colors make a new key every frame
+                    if self.glyph_cache.len() >= TEXT_CACHE_LIMIT {
+                        for (_, stale) in self.glyph_cache.drain() {
+                            // Safe: the canvas that owns the texture is still alive
+                            unsafe { stale.destroy() };
+                        }
+                    }
+                    self.glyph_cache.insert(key, texture);
+                }
+                // Glyphs TTF can't render, like zero-width ones, only advance
+                if let Some(texture) = self.glyph_cache.get(&key) {
+                    let dst = FRect::new(
+                        pen_x,
+                        y as f32,
+                        texture.width() as f32 * scale,
+                        texture.height() as f32 * scale,
+                    );
+                    canvas.copy(texture, None, dst).map_err(|e| e.to_string())?;
+                }
+                pen_x += advance * scale + letter_spacing;
+            }
+            self.draw_stats.text += 1;
+            Ok(())
+        } else {
+            Err("Canvas not initialized".to_string())
+        }
+    }
+
+    // Debug text in the built-in 5x7 font, drawn as filled rects in the current
+    // color. Each glyph cell is 6x8 units, scaled by scale pixels per unit;
+    // newlines start a new row and characters outside ASCII draw as '?'.
+    fn draw_bitmap_text(&mut self, text: &str, x: i32, y: i32, scale: i32) -> Result<(), String> {
+        if let Some(canvas) = &mut self.canvas {
+            let scale = scale.max(1) as f32;
+            let mut pixels = Vec::new();
+            for (row, line) in text.split('\n').enumerate() {
+                for (index, ch) in line.chars().enumerate() {
+                    let glyph = match ch {
+                        ' '..='~' => &BITMAP_FONT[ch as usize - ' ' as usize],
+                        _ => &BITMAP_FONT['?' as usize - ' ' as usize],
+                    };
+                    let cell_x = x as f32 + (index * 6) as f32 * scale;
+                    let cell_y = y as f32 + (row * 8) as f32 * scale;
+                    for (col, bits) in glyph.iter().enumerate() {
+                        for bit in 0..7 {
+                            if bits & (1 << bit) != 0 {
+                                pixels.push(FRect::new(
+                                    cell_x + col as f32 * scale,
+                                    cell_y + bit as f32 * scale,
+                                    scale,
+                                    scale,
+                                ));
+                            }
+                        }
+                    }
+                }
+            }
+            canvas.fill_rects(&pixels).map_err(|e| e.to_string())?;
+            self.draw_stats.text += 1;
+            Ok(())
+        } else {
+            Err("Canvas not initialized".to_string())
+        }
+    }
+
+    // Immediate-mode button: draws itself and returns true on the frame the
+    // left mouse button goes down over it
+    fn button(
+        &mut self,
+        x: i32,
+        y: i32,
+        w: i32,
+        h: i32,
+        label: &str,
+        font: i64,
+    ) -> Result<bool, String> {
+        let hovered = self.is_mouse_in_rect(x, y, w, h)?;
+        let pressed = self
+            .event_pump
+            .as_ref()
+            .is_some_and(|event_pump| event_pump.mouse_state().left());
+        let clicked = hovered && pressed && !self.mouse_left_at_present;
+
+        let label_size = self.measure_text(font, label)?;
+        if let Some(canvas) = &mut self.canvas {
+            let previous = canvas.draw_color();
+            let rect = Rect::new(x, y, w as u32, h as u32);
+            canvas.set_draw_color(if hovered {
+                Color::RGB(90, 90, 90)
+            } else {
+                Color::RGB(60, 60, 60)
+            });
+            canvas.fill_rect(rect).map_err(|e| e.to_string())?;
+            canvas.set_draw_color(Color::RGB(200, 200, 200));
+            canvas.draw_rect(rect.into()).map_err(|e| e.to_string())?;
+            self.draw_stats.rects += 2;
+            canvas.set_draw_color(previous);
+        } else {
+            return Err("Canvas not initialized".to_string());
+        }
+        let label_x = x + (w - label_size.0 as i32) / 2;
+        let label_y = y + (h - label_size.1 as i32) / 2;
+        self.draw_text_colored(font, label, label_x, label_y, Color::RGB(255, 255, 255))?;
+        Ok(clicked)
+    }
+
+    // Background rect with a foreground rect over its left part, fraction
+    // (clamped to 0..1) of the full width
+    #[allow(clippy::too_many_arguments)]
+    fn draw_progress_bar(
+        &mut self,
+        x: i32,
+        y: i32,
+        w: i32,
+        h: i32,
+        fraction: f64,
+        fg_rgb: &Array,
+        bg_rgb: &Array,
+    ) -> Result<(), String> {
+        let fg_color = color_from_array(fg_rgb)?;
+        let bg_color = color_from_array(bg_rgb)?;
+        if let Some(canvas) = &mut self.canvas {
+            let previous = canvas.draw_color();
+            let (w, h) = (w.max(0) as u32, h.max(0) as u32);
+            let filled = (w as f64 * fraction.clamp(0.0, 1.0)).round() as u32;
+            canvas.set_draw_color(bg_color);
+            let mut result = canvas
+                .fill_rect(Rect::new(x, y, w, h))
+                .map_err(|e| e.to_string());
+            if result.is_ok() && filled > 0 {
+                canvas.set_draw_color(fg_color);
+                result = canvas
+                    .fill_rect(Rect::new(x, y, filled, h))
+                    .map_err(|e| e.to_string());
+            }
+            canvas.set_draw_color(previous);
+            if result.is_ok() {
+                self.draw_stats.rects += if filled > 0 { 2 } else { 1 };
+            }
+            result
+        } else {
+            Err("Canvas not initialized".to_string())
+        }
+    }
+
+    fn get_draw_stats(&self) -> Map {
+        self.draw_stats.to_map()
+    }
+
+    // Count frames whose work between presents takes longer than ms, as
+    // reported by get_slow_frames. 0 turns the watchdog off.
+    fn set_frame_budget(&mut self, ms: f64) {
+        self.frame_budget_ms = ms.max(0.0);
+        self.slow_frames = 0;
+        self.worst_frame_ms = 0.0;
+    }
+
+    fn get_slow_frames(&self) -> Map {
+        let mut map = Map::new();
+        map.insert("count".into(), Dynamic::from(self.slow_frames as i64));
+        map.insert("worst_ms".into(), Dynamic::from(self.worst_frame_ms));
+        map
+    }
+
+    fn present(&mut self) -> Result<(), String> {
+        self.finish_texture_loads();
+        if let Some(canvas) = &mut self.canvas {
+            // Time spent on this frame's work, before any limiter sleep
+            if self.frame_budget_ms > 0.0
+                && let Some(last) = self.last_present
+            {
+                let frame_ms = last.elapsed().as_secs_f64() * 1000.0;
+                if frame_ms > self.frame_budget_ms {
+                    self.slow_frames += 1;
+                    self.worst_frame_ms = self.worst_frame_ms.max(frame_ms);
+                }
+            }
+            // Sleep off whatever is left of the frame before presenting. With
+            // VSync on, present already blocks until the next refresh, so the
+            // limiter only acts as a cap when the target is below the refresh rate.
+            if self.target_fps > 0
+                && let Some(last) = self.last_present
+            {
+                let frame_time = Duration::from_secs_f64(1.0 / self.target_fps as f64);
+                let elapsed = last.elapsed();
+                if elapsed < frame_time {
+                    std::thread::sleep(frame_time - elapsed);
+                }
+            }
+            // The back buffer is only readable before it is presented. A failed
+            // save stops the capture and is reported once the frame is shown,
+            // so a full disk doesn't freeze the game.
+            let mut saved = Ok(());
+            if let Some(capture) = &mut self.capture {
+                if capture.frame % capture.every_n_frames == 0 {
+                    let path = capture.dir.join(format!("frame_{:05}.png", capture.saved));
+                    saved = save_canvas(canvas, &path);
+                    capture.saved += 1;
+                }
+                capture.frame += 1;
+            }
+            if saved.is_err() {
+                self.capture = None;
+            }
+            canvas.present();
+            self.script_limits.start_frame();
+            self.draw_stats = DrawStats::default();
+            self.last_present = Some(Instant::now());
+            self.frame_count += 1;
+            if let Some(event_pump) = &self.event_pump {
+                self.mouse_left_at_present = event_pump.mouse_state().left();
+            }
+            saved
+        } else {
+            Err("Canvas not initialized".to_string())
+        }
+    }
+
+    // Camera looking at world point (x, y) with the given zoom. Replaces the
+    // current transform, including one set up by push_transform.
+    fn set_camera(&mut self, x: f32, y: f32, zoom: f32) {
+        self.transform = Transform {
+            offset_x: -x * zoom,
+            offset_y: -y * zoom,
+            scale: zoom,
+        };
+    }
+
+    // Save the current transform and nest a coordinate system whose origin is
+    // (x, y) in the current one, scaled by zoom
+    fn push_transform(&mut self, x: f32, y: f32, zoom: f32) {
+        let current = self.transform;
+        self.transform_stack.push(current);
+        let origin = current.point(x, y);
+        self.transform = Transform {
+            offset_x: origin.x,
+            offset_y: origin.y,
+            scale: current.scale * zoom,
+        };
+    }
+
+    fn pop_transform(&mut self) -> Result<(), String> {
+        self.transform = self
+            .transform_stack
+            .pop()
+            .ok_or("pop_transform without matching push_transform")?;
+        Ok(())
+    }
+
+    // Offset added to the coordinates of the camera-aware draws (see Transform)
+    // until reset_origin, for drawing a widget relative to its top-left corner.
+    // It is applied in world units, before the camera, so rects, lines, thick
+    // and dashed lines, triangles, curves and the scanline-filled shapes behind
+    // them all move together.
+    fn set_origin(&mut self, x: f32, y: f32) {
+        self.origin = (x, y);
+    }
+
+    fn reset_origin(&mut self) {
+        self.origin = (0.0, 0.0);
+    }
+
+    // Camera transform with the origin offset folded in
+    fn draw_transform(&self) -> Transform {
+        let (x, y) = self.origin;
+        let origin = self.transform.point(x, y);
+        Transform {
+            offset_x: origin.x,
+            offset_y: origin.y,
+            scale: self.transform.scale,
+        }
+    }
+
+    // The viewport offsets the origin as well as clipping to the region. Its
+    // rectangle is given in render coordinates, so when a logical size is in
+    // effect it is in logical units rather than window pixels.
+    fn set_viewport(&mut self, x: i32, y: i32, w: i32, h: i32) -> Result<(), String> {
+        if let Some(canvas) = &mut self.canvas {
+            canvas.set_viewport(Rect::new(x, y, w as u32, h as u32));
+            Ok(())
+        } else {
+            Err("Canvas not initialized".to_string())
+        }
+    }
+
+    fn reset_viewport(&mut self) -> Result<(), String> {
+        if let Some(canvas) = &mut self.canvas {
+            canvas.set_viewport(None);
+            Ok(())
+        } else {
+            Err("Canvas not initialized".to_string())
+        }
+    }
+
+    // Snapshot of the drawing state as a map: color [r, g, b, a], blend_mode,
+    // clip and viewport as [x, y, w, h] (or () when unset), camera (the
+    // current transform, not the push_transform stack) and origin [x, y]
+    fn save_state(&self) -> Result<Map, String> {
+        if let Some(canvas) = &self.canvas {
+            let mut state = Map::new();
+            let color = canvas.draw_color();
+            state.insert(
+                "color".into(),
+                [color.r, color.g, color.b, color.a]
+                    .into_iter()
+                    .map(|channel| Dynamic::from(channel as i64))
+                    .collect::<Array>()
+                    .into(),
+            );
+            state.insert(
+                "blend_mode".into(),
+                blend_mode_name(canvas.blend_mode()).into(),
+            );
+            let clip = match canvas.clip_rect() {
+                ClippingRect::Some(rect) => rect_to_array(rect).into(),
+                ClippingRect::Zero => rect_to_array(Rect::new(0, 0, 0, 0)).into(),
+                ClippingRect::None => Dynamic::UNIT,
+            };
+            state.insert("clip".into(), clip);
+            let viewport = if unsafe { SDL_RenderViewportSet(canvas.raw()) } {
+                rect_to_array(canvas.viewport()).into()
+            } else {
+                Dynamic::UNIT
+            };
+            state.insert("viewport".into(), viewport);
+            let mut camera = Map::new();
+            camera.insert(
+                "offset_x".into(),
+                Dynamic::from(self.transform.offset_x as f64),
+            );
+            camera.insert(
+                "offset_y".into(),
+                Dynamic::from(self.transform.offset_y as f64),
+            );
+            camera.insert("scale".into(), Dynamic::from(self.transform.scale as f64));
+            state.insert("camera".into(), camera.into());
+            state.insert(
+                "origin".into(),
+                vec![
+                    Dynamic::from(self.origin.0 as f64),
+                    Dynamic::from(self.origin.1 as f64),
+                ]
+                .into(),
+            );
+            Ok(state)
+        } else {
+            Err("Canvas not initialized".to_string())
+        }
+    }
+
+    // Apply a map from save_state. Missing keys leave that part unchanged.
+    fn restore_state(&mut self, state: &Map) -> Result<(), String> {
+        let Some(canvas) = &mut self.canvas else {
+            return Err("Canvas not initialized".to_string());
+        };
+        if let Some(color) = state.get("color") {
+            let rgba = color
+                .as_array_ref()
+                .map_err(|_| "Expected [r, g, b, a] for color".to_string())?;
+            let channels = rgba
+                .iter()
+                .map(|value| {
+                    value
+                        .as_int()
+                        .map(|c| c as u8)
+                        .map_err(|t| format!("Expected integer color channel, got {}", t))
+                })
+                .collect::<Result<Vec<u8>, String>>()?;
+            let color = match channels[..] {
+                [r, g, b, a] => Color::RGBA(r, g, b, a),
+                [r, g, b] => Color::RGB(r, g, b),
+                _ => return Err("Expected [r, g, b, a] for color".to_string()),
+            };
+            canvas.set_draw_color(color);
+        }
+        if let Some(mode) = state.get("blend_mode") {
+            let mode = mode
+                .clone()
+                .into_string()
+                .map_err(|t| format!("Expected string for blend_mode, got {}", t))?;
+            canvas.set_blend_mode(blend_mode_from_name(&mode)?);
+        }
+        if let Some(clip) = state.get("clip") {
+            if clip.is_unit() {
+                canvas.set_clip_rect(ClippingRect::None);
+            } else {
+                let rect = rect_from_dynamic(clip, "clip")?;
+                if rect.width() == 0 || rect.height() == 0 {
+                    canvas.set_clip_rect(ClippingRect::Zero);
+                } else {
+                    canvas.set_clip_rect(rect);
+                }
+            }
+        }
+        if let Some(viewport) = state.get("viewport") {
+            if viewport.is_unit() {
+                canvas.set_viewport(None);
+            } else {
+                canvas.set_viewport(rect_from_dynamic(viewport, "viewport")?);
+            }
+        }
+        if let Some(camera) = state.get("camera") {
+            let camera = camera.read_lock::<Map>().ok_or("Expected map for camera")?;
+            self.transform = Transform {
+                offset_x: map_number(&camera, "offset_x", 0.0)? as f32,
+                offset_y: map_number(&camera, "offset_y", 0.0)? as f32,
+                scale: map_number(&camera, "scale", 1.0)? as f32,
+            };
+        }
+        if let Some(origin) = state.get("origin") {
+            let origin = origin
+                .as_array_ref()
+                .map_err(|_| "Expected [x, y] for origin".to_string())?;
+            let coordinate = |index: usize| -> Result<f32, String> {
+                let value = origin.get(index).ok_or("Expected [x, y] for origin")?;
+                value
+                    .as_float()
+                    .or_else(|_| value.as_int().map(|v| v as f64))
+                    .map(|v| v as f32)
+                    .map_err(|t| format!("Expected number for origin, got {}", t))
+            };
+            self.origin = (coordinate(0)?, coordinate(1)?);
+        }
+        Ok(())
+    }
+
+    // Render at a fixed virtual resolution of w x h, mapped onto the window by
+    // mode: "letterbox" keeps the aspect ratio with bars, "overscan" keeps it
+    // and crops, "stretch" fills the window, "integer_scale" letterboxes at
+    // whole multiples for crisp pixel art and "disabled" turns it off.
+    fn set_logical_presentation(&mut self, w: u32, h: u32, mode: &str) -> Result<(), String> {
+        let mode = match mode {
+            "disabled" => SDL_LOGICAL_PRESENTATION_DISABLED,
+            "stretch" => SDL_LOGICAL_PRESENTATION_STRETCH,
+            "letterbox" => SDL_LOGICAL_PRESENTATION_LETTERBOX,
+            "overscan" => SDL_LOGICAL_PRESENTATION_OVERSCAN,
+            "integer_scale" => SDL_LOGICAL_PRESENTATION_INTEGER_SCALE,
+            _ => return Err(format!("Unknown logical presentation mode: {}", mode)),
+        };
+        if let Some(canvas) = &mut self.canvas {
+            canvas
+                .set_logical_size(w, h, mode)
+                .map_err(|e| e.to_string())
+        } else {
+            Err("Canvas not initialized".to_string())
+        }
+    }
+
+    // Kept for older scripts: switch the current logical size between
+    // "integer_scale" and "letterbox". Does nothing without a logical size;
+    // set_logical_presentation picks the mode for sizes set later.
+    fn set_integer_scale(&mut self, enabled: bool) -> Result<(), String> {
+        if let Some(canvas) = &self.canvas {
+            let (w, h, mode) = canvas.logical_size();
+            if mode == SDL_LOGICAL_PRESENTATION_DISABLED {
+                return Ok(());
+            }
+            let mode = if enabled {
+                "integer_scale"
+            } else {
+                "letterbox"
+            };
+            self.set_logical_presentation(w, h, mode)
+        } else {
+            Err("Canvas not initialized".to_string())
+        }
+    }
+
+    // Window coordinates such as mouse positions to the logical space drawn
+    // in, as [x, y], accounting for logical presentation and scaling
+    fn window_to_logical(&self, x: f32, y: f32) -> Result<Array, String> {
+        if let Some(canvas) = &self.canvas {
+            let (mut lx, mut ly) = (0.0, 0.0);
+            if !unsafe { SDL_RenderCoordinatesFromWindow(canvas.raw(), x, y, &mut lx, &mut ly) } {
+                return Err(sdl3::get_error().to_string());
+            }
+            Ok(vec![Dynamic::from(lx as f64), Dynamic::from(ly as f64)])
+        } else {
+            Err("Canvas not initialized".to_string())
+        }
+    }
+
+    fn logical_to_window(&self, x: f32, y: f32) -> Result<Array, String> {
+        if let Some(canvas) = &self.canvas {
+            let (mut wx, mut wy) = (0.0, 0.0);
+            if !unsafe { SDL_RenderCoordinatesToWindow(canvas.raw(), x, y, &mut wx, &mut wy) } {
+                return Err(sdl3::get_error().to_string());
+            }
+            Ok(vec![Dynamic::from(wx as f64), Dynamic::from(wy as f64)])
+        } else {
+            Err("Canvas not initialized".to_string())
+        }
+    }
+
+    fn set_target_fps(&mut self, fps: u32) -> Result<(), String> {
+        self.target_fps = fps;
+        self.last_present = None;
+        Ok(())
+    }
+
+    // Present mode: "immediate", "vsync" or "adaptive". Returns the mode that
+    // was applied, which is "vsync" when the driver rejects adaptive vsync.
+    fn set_swap_interval(&mut self, mode: &str) -> Result<String, String> {
+        let interval = match mode {
+            "immediate" => SDL_RENDERER_VSYNC_DISABLED,
+            "vsync" => 1,
+            "adaptive" => SDL_RENDERER_VSYNC_ADAPTIVE,
+            _ => return Err(format!("Unknown swap interval: {}", mode)),
+        };
+        if let Some(canvas) = &mut self.canvas {
+            if unsafe { SDL_SetRenderVSync(canvas.raw(), interval) } {
+                return Ok(mode.to_string());
+            }
+            if interval == SDL_RENDERER_VSYNC_ADAPTIVE
+                && unsafe { SDL_SetRenderVSync(canvas.raw(), 1) }
+            {
+                return Ok("vsync".to_string());
+            }
+            Err(sdl3::get_error().to_string())
+        } else {
+            Err("Canvas not initialized".to_string())
+        }
+    }
+
+    // Switch off the frame limiter and vsync for benchmark_frames, returning
+    // the previous settings for end_benchmark
+    fn begin_benchmark(&mut self) -> Result<(u32, i32), String> {
+        if let Some(canvas) = &mut self.canvas {
+            let mut vsync = 0;
+            unsafe {
+                SDL_GetRenderVSync(canvas.raw(), &mut vsync);
+                SDL_SetRenderVSync(canvas.raw(), 0);
+            }
+            let target_fps = std::mem::replace(&mut self.target_fps, 0);
+            Ok((target_fps, vsync))
+        } else {
+            Err("Canvas not initialized".to_string())
+        }
+    }
+
+    fn end_benchmark(&mut self, (target_fps, vsync): (u32, i32)) {
+        self.target_fps = target_fps;
+        if let Some(canvas) = &mut self.canvas {
+            unsafe { SDL_SetRenderVSync(canvas.raw(), vsync) };
+        }
+    }
+
+    fn save_screenshot(&mut self, path: &str) -> Result<(), String> {
+        if let Some(canvas) = &self.canvas {
+            save_canvas(canvas, Path::new(path))
+        } else {
+            Err("Canvas not initialized".to_string())
+        }
+    }
+
+    fn start_capture(&mut self, dir: &str, every_n_frames: i64) -> Result<(), String> {
+        if every_n_frames <= 0 {
+            return Err("Capture interval must be positive".to_string());
+        }
+        fs::create_dir_all(dir).map_err(|e| format!("Failed to create {}: {}", dir, e))?;
+        self.capture = Some(FrameCapture {
+            dir: PathBuf::from(dir),
+            every_n_frames: every_n_frames as u64,
+            frame: 0,
+            saved: 0,
+        });
+        Ok(())
+    }
+
+    fn stop_capture(&mut self) -> Result<(), String> {
+        self.capture = None;
+        Ok(())
+    }
+
+    fn init_event_pump(&mut self) -> Result<(), String> {
+        self.event_pump = Some(self.sdl.event_pump().map_err(|e| e.to_string())?);
+        // Make sure files dragged onto the window are reported
+        unsafe { SDL_SetEventEnabled(SDL_EVENT_DROP_FILE.0, true) };
+        Ok(())
+    }
+
+    fn poll_event(&mut self) -> Result<bool, String> {
+        match self.poll_event_map()? {
+            Some(event) => Ok(!is_event_type(&event, "quit")),
+            None => Ok(true),
+        }
+    }
+
+    fn next_event(&mut self) -> Result<Dynamic, String> {
+        match self.poll_event_map()? {
+            Some(event) => Ok(Dynamic::from_map(event)),
+            None => Ok(Dynamic::UNIT),
+        }
+    }
+
+    // Drains events like repeatedly calling next_event, but stops once max_ms
+    // of wall-clock time has passed so the rest wait for the next frame.
+    fn poll_events_for(&mut self, max_ms: f64) -> Result<Array, String> {
+        let frequency = sdl3::timer::performance_frequency() as f64;
+        let budget = (max_ms.max(0.0) / 1000.0 * frequency) as u64;
+        let start = sdl3::timer::performance_counter();
+        let mut events = Array::new();
+        while let Some(event) = self.poll_event_map()? {
+            events.push(Dynamic::from_map(event));
+            if sdl3::timer::performance_counter() - start >= budget {
+                break;
+            }
+        }
+        Ok(events)
+    }
+
+    // Next event as a script map. While a replay is active events come from the
+    // replay file instead of SDL; live events are recorded when recording.
+    fn poll_event_map(&mut self) -> Result<Option<Map>, String> {
+        let Some(event_pump) = &mut self.event_pump else {
+            return Err("Event pump not initialized".to_string());
+        };
+
+        if let Some(replay) = &mut self.input_replay {
+            // Live input is dropped while replaying, except for quitting
+            if event_pump
+                .poll_iter()
+                .any(|event| matches!(event, Event::Quit { .. }))
+            {
+                self.input_replay = None;
+                return Ok(Some(event_to_map(&Event::Quit { timestamp: 0 })));
+            }
+            let frame = self.frame_count - replay.start_frame;
+            match replay.events.front() {
+                Some((at, _)) if *at <= frame => {
+                    return Ok(replay.events.pop_front().map(|(_, event)| event));
+                }
+                Some(_) => return Ok(None),
+                // Replay finished, fall back to live input
+                None => self.input_replay = None,
+            }
+        }
+
+        let Some(event) = event_pump.poll_event() else {
+            return Ok(None);
+        };
+        if let Event::Window {
+            win_event: WindowEvent::PixelSizeChanged(w, h),
+            ..
+        } = event
+        {
+            for handle in self.window_sized_layers.clone() {
+                self.resize_layer(handle, w.max(1) as u32, h.max(1) as u32)?;
+            }
+        }
+        let event = event_to_map(&event);
+        if let Some(recorder) = &mut self.input_recorder
+            && !is_event_type(&event, "other")
+        {
+            writeln!(
+                recorder.writer,
+                "{}\t{}",
+                self.frame_count - recorder.start_frame,
+                format_map_as_json(&event)
+            )
+            .map_err(|e| e.to_string())?;
+        }
+        Ok(Some(event))
+    }
+
+    fn start_recording_input(&mut self, path: &str) -> Result<(), String> {
+        let file = File::create(path).map_err(|e| format!("Failed to create {}: {}", path, e))?;
+        self.input_recorder = Some(InputRecorder {
+            writer: BufWriter::new(file),
+            start_frame: self.frame_count,
+        });
+        Ok(())
+    }
+
+    fn stop_recording_input(&mut self) -> Result<(), String> {
+        if let Some(mut recorder) = self.input_recorder.take() {
+            recorder.writer.flush().map_err(|e| e.to_string())?;
+        }
+        Ok(())
+    }
+
+    fn replay_input(&mut self, path: &str) -> Result<(), String> {
+        let contents =
+            fs::read_to_string(path).map_err(|e| format!("Failed to read {}: {}", path, e))?;
+        let parser = Engine::new_raw();
+        let mut events = VecDeque::new();
+        for (number, line) in contents.lines().enumerate() {
+            let invalid = || format!("Invalid input recording {} at line {}", path, number + 1);
+            let (frame, json) = line.split_once('\t').ok_or_else(invalid)?;
+            let frame = frame.parse::<u64>().map_err(|_| invalid())?;
+            let event = parser.parse_json(json, true).map_err(|_| invalid())?;
+            events.push_back((frame, event));
+        }
+        self.input_replay = Some(InputReplay {
+            events,
+            start_frame: self.frame_count,
+        });
+        Ok(())
+    }
+
+    fn is_key_down(&mut self, key: &str) -> Result<bool, String> {
+        if let Some(event_pump) = &mut self.event_pump {
+            let scancode = match key.to_lowercase().as_str() {
+                "space" => Scancode::Space,
+                "left" => Scancode::Left,
+                "right" => Scancode::Right,
+                "up" => Scancode::Up,
+                "down" => Scancode::Down,
+                _ => return Err(format!("Unsupported key: {}", key)),
+            };
+            let keyboard_state = event_pump.keyboard_state();
+            Ok(keyboard_state.pressed_scancodes().any(|s| s == scancode))
+        } else {
+            Err("Event pump not initialized".to_string())
+        }
+    }
+
+    // Label the current keyboard layout gives the key at a physical position,
+    // e.g. the scancode of QWERTY's Q reads "A" on AZERTY. Needs video, since
+    // SDL learns the layout from the windowing system.
+    fn get_key_name_for_scancode(&mut self, code: i64) -> Result<String, String> {
+        self.video()?;
+        let scancode = i32::try_from(code)
+            .ok()
+            .and_then(Scancode::from_i32)
+            .ok_or_else(|| format!("Invalid scancode: {}", code))?;
+        Ok(Keycode::from_scancode(scancode, SDL_KMOD_NONE, false)
+            .map(|keycode| keycode.name())
+            .unwrap_or_default())
+    }
+
+    fn is_mouse_button_down(&mut self, button: &str) -> Result<bool, String> {
+        if let Some(event_pump) = &mut self.event_pump {
+            let mouse_state = event_pump.mouse_state();
+            let button_mask = match button.to_lowercase().as_str() {
+                "left" => MouseButton::Left,
+                "right" => MouseButton::Right,
+                "middle" => MouseButton::Middle,
+                _ => return Err(format!("Unsupported mouse button: {}", button)),
+            };
+            Ok(mouse_state.is_mouse_button_pressed(button_mask))
+        } else {
+            Err("Event pump not initialized".to_string())
+        }
+    }
+
+    fn get_pressed_mouse_buttons(&mut self) -> Result<Array, String> {
+        if let Some(event_pump) = &mut self.event_pump {
+            Ok(event_pump
+                .mouse_state()
+                .pressed_mouse_buttons()
+                .map(|button| Dynamic::from(mouse_button_name(button).to_string()))
+                .collect())
+        } else {
+            Err("Event pump not initialized".to_string())
+        }
+    }
+
+    fn get_mouse_position(&mut self) -> Result<(i64, i64), String> {
+        if let Some(event_pump) = &mut self.event_pump {
+            let mouse_state = event_pump.mouse_state();
+            Ok((mouse_state.x() as i64, mouse_state.y() as i64))
+        } else {
+            Err("Event pump not initialized".to_string())
+        }
+    }
+
+    // Hide and lock the cursor to the window while reporting unbounded
+    // motion, for mouse-look controls
+    fn set_relative_mouse_mode(&mut self, enabled: bool) -> Result<(), String> {
+        if let Some(window) = &self.window {
+            self.sdl.mouse().set_relative_mouse_mode(window, enabled);
+            Ok(())
+        } else {
+            Err("Window not initialized".to_string())
+        }
+    }
+
+    // Mouse motion since the last call as [dx, dy], multiplied by the factor
+    // from set_relative_mouse_sensitivity
+    fn get_relative_mouse_state(&mut self) -> Result<Array, String> {
+        if let Some(event_pump) = &self.event_pump {
+            let state = event_pump.relative_mouse_state();
+            let factor = self.relative_mouse_sensitivity;
+            Ok(vec![
+                Dynamic::from(state.x() as f64 * factor),
+                Dynamic::from(state.y() as f64 * factor),
+            ])
+        } else {
+            Err("Event pump not initialized".to_string())
+        }
+    }
+
+    fn set_relative_mouse_sensitivity(&mut self, factor: f64) -> Result<(), String> {
+        if factor <= 0.0 {
+            return Err(format!(
+                "Mouse sensitivity must be positive, got {}",
+                factor
+            ));
+        }
+        self.relative_mouse_sensitivity = factor;
+        Ok(())
+    }
+
+    fn is_mouse_in_rect(&mut self, x: i32, y: i32, w: i32, h: i32) -> Result<bool, String> {
+        if let Some(event_pump) = &mut self.event_pump {
+            let mouse_state = event_pump.mouse_state();
+            let (mx, my) = (mouse_state.x() as i64, mouse_state.y() as i64);
+            Ok(rect_contains_point(
+                (x as i64, y as i64, w as i64, h as i64),
+                mx,
+                my,
+            ))
+        } else {
+            Err("Event pump not initialized".to_string())
+        }
+    }
+
+    fn gamepads(&mut self) -> Result<&GamepadSubsystem, String> {
+        if self.gamepad_subsystem.is_none() {
+            self.gamepad_subsystem = Some(self.sdl.gamepad().map_err(|e| e.to_string())?);
+        }
+        Ok(self.gamepad_subsystem.as_ref().unwrap())
+    }
+
+    fn list_gamepads(&mut self) -> Result<Array, String> {
+        let gamepads = self.gamepads()?;
+        let ids = gamepads.gamepads().map_err(|e| e.to_string())?;
+        Ok(ids
+            .into_iter()
+            .filter_map(|id| gamepads.name_for_id(id).ok())
+            .map(Dynamic::from)
+            .collect())
+    }
+
+    fn open_gamepad(&mut self, index: i64) -> Result<(), String> {
+        let gamepads = self.gamepads()?;
+        let ids = gamepads.gamepads().map_err(|e| e.to_string())?;
+        let id = usize::try_from(index)
+            .ok()
+            .and_then(|i| ids.get(i).copied())
+            .ok_or_else(|| format!("No gamepad at index {}", index))?;
+        let gamepad = gamepads.open(id).map_err(|e| e.to_string())?;
+        self.gamepad = Some(gamepad);
+        Ok(())
+    }
+
+    /// Raw handle of the open gamepad, for the sensor and touchpad calls the
+    /// safe wrapper only exposes behind its `hidapi` feature.
+    fn raw_gamepad(&self) -> Result<*mut SDL_Gamepad, String> {
+        let gamepad = self.gamepad.as_ref().ok_or("No gamepad open")?;
+        let id = gamepad.id().map_err(|e| e.to_string())?;
+        let raw = unsafe { SDL_GetGamepadFromID(id) };
+        if raw.is_null() {
+            Err(sdl3::get_error().to_string())
+        } else {
+            Ok(raw)
+        }
+    }
+
+    fn gamepad_sensor(&mut self, name: &str) -> Result<Array, String> {
+        let sensor = match name {
+            "gyro" => SDL_SENSOR_GYRO,
+            "accel" => SDL_SENSOR_ACCEL,
+            _ => return Err(format!("Unknown sensor: {}", name)),
+        };
+        let raw = self.raw_gamepad()?;
+        unsafe {
+            if !SDL_GamepadHasSensor(raw, sensor) {
+                return Err(format!("Gamepad has no {} sensor", name));
+            }
+            if !SDL_GamepadSensorEnabled(raw, sensor)
+                && !SDL_SetGamepadSensorEnabled(raw, sensor, true)
+            {
+                return Err(sdl3::get_error().to_string());
+            }
+            let mut data = [0.0f32; 3];
+            if !SDL_GetGamepadSensorData(raw, sensor, data.as_mut_ptr(), data.len() as i32) {
+                return Err(sdl3::get_error().to_string());
+            }
+            Ok(data.iter().map(|&v| Dynamic::from(v as f64)).collect())
+        }
+    }
+
+    fn gamepad_touchpad(&self, index: i64) -> Result<Array, String> {
+        let raw = self.raw_gamepad()?;
+        let mut fingers = Array::new();
+        // Indexes past i32 are as absent as any other out-of-range touchpad
+        let Ok(touchpad) = i32::try_from(index) else {
+            return Ok(fingers);
+        };
+        unsafe {
+            if touchpad < 0 || touchpad >= SDL_GetNumGamepadTouchpads(raw) {
+                return Ok(fingers);
+            }
+            for finger in 0..SDL_GetNumGamepadTouchpadFingers(raw, touchpad) {
+                let (mut down, mut x, mut y, mut pressure) = (false, 0.0f32, 0.0f32, 0.0f32);
+                if SDL_GetGamepadTouchpadFinger(
+                    raw,
+                    touchpad,
+                    finger,
+                    &mut down,
+                    &mut x,
+                    &mut y,
+                    &mut pressure,
+                ) && down
+                {
+                    let mut map = Map::new();
+                    map.insert("finger".into(), Dynamic::from(finger as i64));
+                    map.insert("x".into(), Dynamic::from(x as f64));
+                    map.insert("y".into(), Dynamic::from(y as f64));
+                    map.insert("pressure".into(), Dynamic::from(pressure as f64));
+                    fingers.push(Dynamic::from(map));
+                }
+            }
+        }
+        Ok(fingers)
+    }
+
+    fn list_audio_devices(&self) -> Result<Array, String> {
+        let audio = self.sdl.audio().map_err(|e| e.to_string())?;
+        let ids = audio
+            .audio_playback_device_ids()
+            .map_err(|e| e.to_string())?;
+        Ok(ids
+            .into_iter()
+            .filter_map(|id| id.name().ok())
+            .map(Dynamic::from)
+            .collect())
+    }
+
+    // Samples are 32-bit float, interleaved by channel. samples sets the
+    // device buffer size in sample frames, 0 leaves it to SDL. The stream
+    // plays on the device chosen by the last open_audio_stream_on, or the
+    // default output.
+    fn open_audio_stream(&mut self, freq: i64, channels: i64, samples: i64) -> Result<(), String> {
+        let freq = i32::try_from(freq)
+            .ok()
+            .filter(|&freq| freq > 0)
+            .ok_or_else(|| format!("Invalid sample rate: {}", freq))?;
+        let channels = i32::try_from(channels)
+            .ok()
+            .filter(|&channels| channels > 0)
+            .ok_or_else(|| format!("Invalid channel count: {}", channels))?;
+        let samples =
+            u32::try_from(samples).map_err(|_| format!("Invalid buffer size: {}", samples))?;
+        let audio = self.sdl.audio().map_err(|e| e.to_string())?;
+        // The hint is process-wide, so a 0 must clear what an earlier call set
+        if samples > 0 {
+            sdl3::hint::set("SDL_AUDIO_DEVICE_SAMPLE_FRAMES", &samples.to_string());
+        } else {
+            unsafe { SDL_ResetHint(SDL_HINT_AUDIO_DEVICE_SAMPLE_FRAMES) };
+        }
+        let device = match &self.audio_device {
+            Some(name) => {
+                let id = audio
+                    .audio_playback_device_ids()
+                    .map_err(|e| e.to_string())?
+                    .into_iter()
+                    .find(|id| id.name().as_deref() == Ok(name.as_str()))
+                    .ok_or_else(|| format!("Unknown audio device: {}", name))?;
+                AudioDevice::new(id, audio)
+            }
+            None => audio.default_playback_device(),
+        };
+        let spec = AudioSpec::new(Some(freq), Some(channels), Some(AudioFormat::f32_sys()));
+        let stream = device
+            .open_device_stream(Some(&spec))
+            .map_err(|e| e.to_string())?;
+        stream.resume().map_err(|e| e.to_string())?;
+        self.audio_stream = Some(stream);
+        Ok(())
+    }
+
+    // Pick the output device by a name from list_audio_devices, "" for the
+    // default output, and open the stream on it
+    fn open_audio_stream_on(
+        &mut self,
+        freq: i64,
+        channels: i64,
+        samples: i64,
+        device: &str,
+    ) -> Result<(), String> {
+        self.audio_device = (!device.is_empty()).then(|| device.to_string());
+        self.open_audio_stream(freq, channels, samples)
+    }
+
+    // Floats are used as-is in -1.0..1.0, integers are treated as i16 samples
+    fn queue_audio(&mut self, samples: &Array) -> Result<(), String> {
+        if let Some(stream) = &self.audio_stream {
+            let data = samples
+                .iter()
+                .map(|sample| {
+                    if let Ok(value) = sample.as_float() {
+                        Ok(value as f32)
+                    } else if let Ok(value) = sample.as_int() {
+                        Ok(value.clamp(i16::MIN as i64, i16::MAX as i64) as f32 / 32768.0)
+                    } else {
+                        Err(format!("Invalid audio sample: {}", sample))
+                    }
+                })
+                .collect::<Result<Vec<f32>, String>>()?;
+            stream.put_data_f32(&data).map_err(|e| e.to_string())
+        } else {
+            Err("Audio stream not initialized".to_string())
+        }
+    }
+
+    fn delay(&self, ms: u32) -> Result<(), String> {
+        std::thread::sleep(Duration::from_millis(ms as u64));
+        Ok(())
+    }
+
+    fn after(&mut self, ms: u64, callback: FnPtr) -> Result<(), String> {
+        let due = Instant::now() + Duration::from_millis(ms);
+        self.timers.push((due, callback));
+        Ok(())
+    }
+
+    fn take_due_timers(&mut self) -> Vec<FnPtr> {
+        let now = Instant::now();
+        let (due, pending): (Vec<_>, Vec<_>) =
+            self.timers.drain(..).partition(|(at, _)| *at <= now);
+        self.timers = pending;
+        due.into_iter().map(|(_, callback)| callback).collect()
+    }
+
+    fn register_scene(&mut self, name: &str, init: FnPtr, update: FnPtr, draw: FnPtr) {
+        self.scenes
+            .insert(name.to_string(), Scene { init, update, draw });
+    }
+
+    fn goto_scene(&mut self, name: &str) -> Result<(), String> {
+        if !self.scenes.contains_key(name) {
+            return Err(format!("Unknown scene: {}", name));
+        }
+        self.next_scene = Some(name.to_string());
+        Ok(())
+    }
+
+    // Apply a pending goto_scene, returning the init callback to run, then
+    // the active scene's update and draw callbacks
+    fn scene_frame(&mut self) -> Result<(Option<FnPtr>, FnPtr, FnPtr), String> {
+        let mut init = None;
+        if let Some(name) = self.next_scene.take() {
+            init = self.scenes.get(&name).map(|scene| scene.init.clone());
+            self.active_scene = Some(name);
+        }
+        let scene = self
+            .active_scene
+            .as_ref()
+            .and_then(|name| self.scenes.get(name))
+            .ok_or("No active scene, call goto_scene first")?;
+        Ok((init, scene.update.clone(), scene.draw.clone()))
+    }
+
+    // Parse a script file once so run_compiled can run it every frame
+    // without recompiling
+    fn compile_script(&mut self, engine: &Engine, path: &str) -> Result<i64, String> {
+        let ast = engine
+            .compile_file(path.into())
+            .map_err(|e| format!("Failed to compile {}: {}", path, e))?;
+        let id = self.next_script_id;
+        self.next_script_id += 1;
+        self.compiled_scripts.insert(id, ast);
+        Ok(id)
+    }
+
+    // Compile a script like compile_script and remember its file, so
+    // reload_changed_scripts picks up edits
+    fn watch_script(&mut self, engine: &Engine, path: &str) -> Result<i64, String> {
+        let id = self.compile_script(engine, path)?;
+        let modified = fs::metadata(path).and_then(|m| m.modified()).ok();
+        self.watched_scripts
+            .insert(id, (PathBuf::from(path), modified));
+        Ok(id)
+    }
+
+    // Recompile watched scripts whose file changed since the last check,
+    // returning #{ handle, path, ok, error } for each one. A script that fails
+    // to compile keeps running its previous version until the file is fixed.
+    fn reload_changed_scripts(&mut self, engine: &Engine) -> Array {
+        let mut reports = Array::new();
+        for (&id, (path, last_modified)) in &mut self.watched_scripts {
+            let modified = fs::metadata(&*path).and_then(|m| m.modified()).ok();
+            if modified == *last_modified {
+                continue;
+            }
+            *last_modified = modified;
+            let mut report = Map::new();
+            report.insert("handle".into(), Dynamic::from(id));
+            report.insert(
+                "path".into(),
+                Dynamic::from(path.to_string_lossy().into_owned()),
+            );
+            match engine.compile_file(path.clone()) {
+                Ok(ast) => {
+                    self.compiled_scripts.insert(id, ast);
+                    report.insert("ok".into(), Dynamic::from(true));
+                }
+                Err(e) => {
+                    report.insert("ok".into(), Dynamic::from(false));
+                    report.insert("error".into(), Dynamic::from(e.to_string()));
+                }
+            }
+            reports.push(Dynamic::from_map(report));
+        }
+        reports
+    }
+
+    // The script to run and the shared scope, which run_compiled hands back
+    // with end_compiled_run once the script finishes
+    fn begin_compiled_run(&mut self, handle: i64) -> Result<(AST, Scope<'static>), String> {
+        let ast = self
+            .compiled_scripts
+            .get(&handle)
+            .cloned()
+            .ok_or_else(|| format!("Invalid script handle: {}", handle))?;
+        Ok((ast, std::mem::take(&mut self.script_scope)))
+    }
+
+    fn end_compiled_run(&mut self, scope: Scope<'static>) {
+        self.script_scope = scope;
+    }
+
+    // Interrupt scripts that run more than max_ops operations or max_seconds
+    // without presenting a frame, catching infinite loops. 0 means no limit.
+    // The error ends the whole evaluation and reaches only the host.
+    fn set_script_limits(&mut self, max_ops: u64, max_seconds: f64) {
+        let max_time = (max_seconds > 0.0).then(|| Duration::from_secs_f64(max_seconds));
+        self.script_limits.set(max_ops, max_time);
+    }
+
+    // Pump pending events and check for a quit request without consuming it,
+    // so scripts reading events with next_event still see everything
+    fn quit_requested(&mut self) -> Result<bool, String> {
+        if let Some(event_pump) = &mut self.event_pump {
+            event_pump.pump_events();
+            Ok(unsafe { SDL_HasEvent(SDL_EVENT_QUIT.0) })
+        } else {
+            Err("Event pump not initialized".to_string())
+        }
+    }
+
+    // Discard pending input after a loading pause. Quit events are kept so a
+    // close request made during the pause is not lost.
+    fn flush_events(&mut self) -> Result<(), String> {
+        if let Some(event_pump) = &mut self.event_pump {
+            event_pump.pump_events();
+            unsafe { SDL_FlushEvents(SDL_EVENT_QUIT.0 + 1, SDL_EVENT_LAST.0) };
+            Ok(())
+        } else {
+            Err("Event pump not initialized".to_string())
+        }
+    }
+
+    fn grid_new(&mut self, cell_size: f64) -> Result<i64, String> {
+        if cell_size <= 0.0 {
+            return Err("Grid cell size must be positive".to_string());
+        }
+        let id = self.next_grid_id;
+        self.next_grid_id += 1;
+        self.grids.insert(
+            id,
+            SpatialGrid {
+                cell_size,
+                cells: HashMap::new(),
+            },
+        );
+        Ok(id)
+    }
+
+    fn grid(&mut self, grid: i64) -> Result<&mut SpatialGrid, String> {
+        self.grids
+            .get_mut(&grid)
+            .ok_or_else(|| format!("Invalid grid handle: {}", grid))
+    }
+
+    // Restart the random sequence from seed, so runs with the same seed and
+    // input make the same random choices
+    fn seed_rng(&mut self, seed: i64) {
+        self.rng = StdRng::seed_from_u64(seed as u64);
+    }
+
+    fn rand(&mut self, min: i64, max: i64) -> Result<i64, String> {
+        if min > max {
+            return Err(format!("rand range is empty: {} > {}", min, max));
+        }
+        Ok(self.rng.random_range(min..=max))
+    }
+
+    // Fisher-Yates shuffled copy of items
+    fn shuffle(&mut self, items: &Array) -> Array {
+        let mut items = items.clone();
+        for i in (1..items.len()).rev() {
+            let j = self.rng.random_range(0..=i);
+            items.swap(i, j);
+        }
+        items
+    }
+
+    fn choose(&mut self, items: &Array) -> Result<Dynamic, String> {
+        if items.is_empty() {
+            return Err("Cannot choose from an empty array".to_string());
+        }
+        Ok(items[self.rng.random_range(0..items.len())].clone())
+    }
+}
+
+// Built-in 5x7 font for draw_bitmap_text, printable ASCII from ' ' to '~'.
+// Each glyph is five columns, left to right, with bit 0 as the top row.
+const BITMAP_FONT: [[u8; 5]; 95] = [
+    [0x00, 0x00, 0x00, 0x00, 0x00], // ' '
+    [0x00, 0x00, 0x5F, 0x00, 0x00], // '!'
+    [0x00, 0x07, 0x00, 0x07, 0x00], // '"'
+    [0x14, 0x7F, 0x14, 0x7F, 0x14], // '#'
+    [0x24, 0x2A, 0x7F, 0x2A, 0x12], // '$'
+    [0x23, 0x13, 0x08, 0x64, 0x62], // '%'
+    [0x36, 0x49, 0x55, 0x22, 0x50], // '&'
+    [0x00, 0x05, 0x03, 0x00, 0x00], // '\''
+    [0x00, 0x1C, 0x22, 0x41, 0x00], // '('
+    [0x00, 0x41, 0x22, 0x1C, 0x00], // ')'
+    [0x08, 0x2A, 0x1C, 0x2A, 0x08], // '*'
+    [0x08, 0x08, 0x3E, 0x08, 0x08], // '+'
+    [0x00, 0x50, 0x30, 0x00, 0x00], // ','
+    [0x08, 0x08, 0x08, 0x08, 0x08], // '-'
+    [0x00, 0x60, 0x60, 0x00, 0x00], // '.'
+    [0x20, 0x10, 0x08, 0x04, 0x02], // '/'
+    [0x3E, 0x51, 0x49, 0x45, 0x3E], // '0'
+    [0x00, 0x42, 0x7F, 0x40, 0x00], // '1'
+    [0x42, 0x61, 0x51, 0x49, 0x46], // '2'
+    [0x21, 0x41, 0x45, 0x4B, 0x31], // '3'
+    [0x18, 0x14, 0x12, 0x7F, 0x10], // '4'
+    [0x27, 0x45, 0x45, 0x45, 0x39], // '5'
+    [0x3C, 0x4A, 0x49, 0x49, 0x30], // '6'
+    [0x01, 0x71, 0x09, 0x05, 0x03], // '7'
+    [0x36, 0x49, 0x49, 0x49, 0x36], // '8'
+    [0x06, 0x49, 0x49, 0x29, 0x1E], // '9'
+    [0x00, 0x36, 0x36, 0x00, 0x00], // ':'
+    [0x00, 0x56, 0x36, 0x00, 0x00], // ';'
+    [0x08, 0x14, 0x22, 0x41, 0x00], // '<'
+    [0x14, 0x14, 0x14, 0x14, 0x14], // '='
+    [0x00, 0x41, 0x22, 0x14, 0x08], // '>'
+    [0x02, 0x01, 0x51, 0x09, 0x06], // '?'
+    [0x32, 0x49, 0x79, 0x41, 0x3E], // '@'
+    [0x7E, 0x11, 0x11, 0x11, 0x7E], // 'A'
+    [0x7F, 0x49, 0x49, 0x49, 0x36], // 'B'
+    [0x3E, 0x41, 0x41, 0x41, 0x22], // 'C'
+    [0x7F, 0x41, 0x41, 0x22, 0x1C], // 'D'
+    [0x7F, 0x49, 0x49, 0x49, 0x41], // 'E'
+    [0x7F, 0x09, 0x09, 0x01, 0x01], // 'F'
+    [0x3E, 0x41, 0x41, 0x51, 0x32], // 'G'
+    [0x7F, 0x08, 0x08, 0x08, 0x7F], // 'H'
+    [0x00, 0x41, 0x7F, 0x41, 0x00], // 'I'
+    [0x20, 0x40, 0x41, 0x3F, 0x01], // 'J'
+    [0x7F, 0x08, 0x14, 0x22, 0x41], // 'K'
+    [0x7F, 0x40, 0x40, 0x40, 0x40], // 'L'
+    [0x7F, 0x02, 0x04, 0x02, 0x7F], // 'M'
+    [0x7F, 0x04, 0x08, 0x10, 0x7F], // 'N'
+    [0x3E, 0x41, 0x41, 0x41, 0x3E], // 'O'
+    [0x7F, 0x09, 0x09, 0x09, 0x06], // 'P'
+    [0x3E, 0x41, 0x51, 0x21, 0x5E], // 'Q'
+    [0x7F, 0x09, 0x19, 0x29, 0x46], // 'R'
+    [0x46, 0x49, 0x49, 0x49, 0x31], // 'S'
+    [0x01, 0x01, 0x7F, 0x01, 0x01], // 'T'
+    [0x3F, 0x40, 0x40, 0x40, 0x3F], // 'U'
+    [0x1F, 0x20, 0x40, 0x20, 0x1F], // 'V'
+    [0x7F, 0x20, 0x18, 0x20, 0x7F], // 'W'
+    [0x63, 0x14, 0x08, 0x14, 0x63], // 'X'
+    [0x03, 0x04, 0x78, 0x04, 0x03], // 'Y'
+    [0x61, 0x51, 0x49, 0x45, 0x43], // 'Z'
+    [0x00, 0x7F, 0x41, 0x41, 0x00], // '['
+    [0x02, 0x04, 0x08, 0x10, 0x20], // '\\'
+    [0x00, 0x41, 0x41, 0x7F, 0x00], // ']'
+    [0x04, 0x02, 0x01, 0x02, 0x04], // '^'
+    [0x40, 0x40, 0x40, 0x40, 0x40], // '_'
+    [0x00, 0x01, 0x02, 0x04, 0x00], // '`'
+    [0x20, 0x54, 0x54, 0x54, 0x78], // 'a'
+    [0x7F, 0x48, 0x44, 0x44, 0x38], // 'b'
+    [0x38, 0x44, 0x44, 0x44, 0x20], // 'c'
+    [0x38, 0x44, 0x44, 0x48, 0x7F], // 'd'
+    [0x38, 0x54, 0x54, 0x54, 0x18], // 'e'
+    [0x08, 0x7E, 0x09, 0x01, 0x02], // 'f'
+    [0x0C, 0x52, 0x52, 0x52, 0x3E], // 'g'
+    [0x7F, 0x08, 0x04, 0x04, 0x78], // 'h'
+    [0x00, 0x44, 0x7D, 0x40, 0x00], // 'i'
+    [0x20, 0x40, 0x44, 0x3D, 0x00], // 'j'
+    [0x00, 0x7F, 0x10, 0x28, 0x44], // 'k'
+    [0x00, 0x41, 0x7F, 0x40, 0x00], // 'l'
+    [0x7C, 0x04, 0x18, 0x04, 0x78], // 'm'
+    [0x7C, 0x08, 0x04, 0x04, 0x78], // 'n'
+    [0x38, 0x44, 0x44, 0x44, 0x38], // 'o'
+    [0x7C, 0x14, 0x14, 0x14, 0x08], // 'p'
+    [0x08, 0x14, 0x14, 0x18, 0x7C], // 'q'
+    [0x7C, 0x08, 0x04, 0x04, 0x08], // 'r'
+    [0x48, 0x54, 0x54, 0x54, 0x20], // 's'
+    [0x04, 0x3F, 0x44, 0x40, 0x20], // 't'
+    [0x3C, 0x40, 0x40, 0x20, 0x7C], // 'u'
+    [0x1C, 0x20, 0x40, 0x20, 0x1C], // 'v'
+    [0x3C, 0x40, 0x30, 0x40, 0x3C], // 'w'
+    [0x44, 0x28, 0x10, 0x28, 0x44], // 'x'
+    [0x0C, 0x50, 0x50, 0x50, 0x3C], // 'y'
+    [0x44, 0x64, 0x54, 0x4C, 0x44], // 'z'
+    [0x00, 0x08, 0x36, 0x41, 0x00], // '{'
+    [0x00, 0x00, 0x7F, 0x00, 0x00], // '|'
+    [0x00, 0x41, 0x36, 0x08, 0x00], // '}'
+    [0x08, 0x04, 0x08, 0x10, 0x08], // '~'
+];
+
+// Name, version and reverse-domain identifier SDL reports for this app, used
+// for things like the Wayland app id and audio stream names
+fn set_app_metadata(name: &str, version: &str, identifier: &str) -> Result<(), String> {
+    let c_string = |s: &str| CString::new(s).map_err(|e| e.to_string());
+    let (name, version, identifier) = (c_string(name)?, c_string(version)?, c_string(identifier)?);
+    if unsafe { SDL_SetAppMetadata(name.as_ptr(), version.as_ptr(), identifier.as_ptr()) } {
+        Ok(())
+    } else {
+        Err(sdl3::get_error().to_string())
+    }
+}
+
+// Transparent RGBA render target, alpha blended when drawn. The renderer's
+// current target is left as it was.
+fn blank_target_texture(canvas: &mut Canvas<Window>, w: u32, h: u32) -> Result<Texture, String> {
+    let mut texture = canvas
+        .texture_creator()
+        .create_texture_target(pixel_format_from_name("RGBA32")?, w.max(1), h.max(1))
+        .map_err(|e| e.to_string())?;
+    texture.set_blend_mode(BlendMode::Blend);
+    canvas
+        .with_texture_canvas(&mut texture, |target_canvas| {
+            target_canvas.set_draw_color(Color::RGBA(0, 0, 0, 0));
+            target_canvas.clear();
+        })
+        .map_err(|e| e.to_string())?;
+    Ok(texture)
+}
+
+// Syntax check for check_script: #{ ok: true }, or #{ ok: false, line,
+// column, message } for the first parse error. line and column count from 1
+// and are 0 when Rhai reports no position.
+fn check_script(engine: &Engine, source: &str) -> Map {
+    let mut report = Map::new();
+    match engine.compile(source) {
+        Ok(_) => {
+            report.insert("ok".into(), Dynamic::from(true));
+        }
+        Err(e) => {
+            let position = e.position();
+            report.insert("ok".into(), Dynamic::from(false));
+            report.insert(
+                "line".into(),
+                Dynamic::from(position.line().unwrap_or(0) as i64),
+            );
+            report.insert(
+                "column".into(),
+                Dynamic::from(position.position().unwrap_or(0) as i64),
+            );
+            report.insert("message".into(), Dynamic::from(e.err_type().to_string()));
+        }
+    }
+    report
+}
+
+// Frame loop behind run_loop and run_scenes. Each iteration fires due `after`
+// callbacks, clears the canvas unless disabled with set_auto_clear, calls
+// frame(dt) with the elapsed seconds and presents, until the window is closed
+// or frame returns false. The context lock is released while frame runs,
+// since script callbacks call back into it.
+fn run_frames(
+    context: &NativeCallContext,
+    sdl_context: &Arc<Mutex<SDLContext>>,
+    mut frame: impl FnMut(f64) -> Result<bool, Box<EvalAltResult>>,
+) -> Result<(), Box<EvalAltResult>> {
+    let lock = || sdl_context.lock().unwrap_or_else(PoisonError::into_inner);
+    let to_error = |e: String| {
+        Box::new(EvalAltResult::ErrorRuntime(
+            Dynamic::from(e),
+            Default::default(),
+        ))
+    };
+
+    let mut last_frame = Instant::now();
+    loop {
+        if lock().quit_requested().map_err(to_error)? {
+            break;
+        }
+
+        let due = lock().take_due_timers();
+        for callback in due {
+            let _ = callback.call_within_context::<Dynamic>(context, ())?;
+        }
+
+        lock().auto_clear_frame().map_err(to_error)?;
+
+        let now = Instant::now();
+        let dt = now.duration_since(last_frame).as_secs_f64();
+        last_frame = now;
+        if !frame(dt)? {
+            break;
+        }
+
+        lock().present().map_err(to_error)?;
+    }
+    Ok(())
+}
+
+// Battery state for get_power_info. percent and seconds (of battery life
+// left) are -1 when the platform can't tell.
+fn power_info() -> Result<Map, String> {
+    let (mut seconds, mut percent) = (-1, -1);
+    let state = match unsafe { SDL_GetPowerInfo(&mut seconds, &mut percent) } {
+        SDL_POWERSTATE_ON_BATTERY => "on_battery",
+        SDL_POWERSTATE_CHARGING => "charging",
+        SDL_POWERSTATE_CHARGED => "charged",
+        SDL_POWERSTATE_NO_BATTERY => "no_battery",
+        SDL_POWERSTATE_ERROR => return Err(sdl3::get_error().to_string()),
+        _ => "unknown",
+    };
+    let mut map = Map::new();
+    map.insert("state".into(), Dynamic::from(state.to_string()));
+    map.insert("percent".into(), Dynamic::from(percent as i64));
+    map.insert("seconds".into(), Dynamic::from(seconds as i64));
+    Ok(map)
+}
+
+// Standard easing curves for t clamped to 0..1, mapping 0 to 0 and 1 to 1
+fn ease(name: &str, t: f64) -> Result<f64, String> {
+    let t = t.clamp(0.0, 1.0);
+    let value = match name {
+        "linear" => t,
+        "ease_in_quad" => t * t,
+        "ease_out_quad" => t * (2.0 - t),
+        "ease_in_out_quad" => {
+            if t < 0.5 {
+                2.0 * t * t
+            } else {
+                1.0 - (-2.0 * t + 2.0).powi(2) / 2.0
+            }
+        }
+        "ease_in_cubic" => t.powi(3),
+        "ease_out_cubic" => 1.0 - (1.0 - t).powi(3),
+        "ease_in_out_cubic" => {
+            if t < 0.5 {
+                4.0 * t.powi(3)
+            } else {
+                1.0 - (-2.0 * t + 2.0).powi(3) / 2.0
+            }
+        }
+        "ease_in_sine" => 1.0 - (t * std::f64::consts::FRAC_PI_2).cos(),
+        "ease_out_sine" => (t * std::f64::consts::FRAC_PI_2).sin(),
+        "ease_in_out_sine" => -((std::f64::consts::PI * t).cos() - 1.0) / 2.0,
+        "ease_out_bounce" => {
+            let (n, d) = (7.5625, 2.75);
+            if t < 1.0 / d {
+                n * t * t
+            } else if t < 2.0 / d {
+                let t = t - 1.5 / d;
+                n * t * t + 0.75
+            } else if t < 2.5 / d {
+                let t = t - 2.25 / d;
+                n * t * t + 0.9375
+            } else {
+                let t = t - 2.625 / d;
+                n * t * t + 0.984375
+            }
+        }
+        _ => return Err(format!("Unknown easing: {}", name)),
+    };
+    Ok(value)
+}
+
+// Offsets of the midpoint circle algorithm for one octant, from (radius, 0)
+// up to the diagonal, with x >= y >= 0. Mirroring them gives the whole circle.
+fn circle_octant(radius: i32) -> Vec<(i32, i32)> {
+    let mut offsets = Vec::new();
+    let (mut x, mut y) = (radius, 0);
+    let mut error = 1 - radius;
+    while x >= y {
+        offsets.push((x, y));
+        y += 1;
+        if error < 0 {
+            error += 2 * y + 1;
+        } else {
+            x -= 1;
+            error += 2 * (y - x) + 1;
+        }
+    }
+    offsets
+}
+
+// Cells a line from (x0, y0) to (x1, y1) passes through, both ends included,
+// by Bresenham's algorithm. Consecutive cells touch by an edge or a corner.
+fn line_cells(x0: i64, y0: i64, x1: i64, y1: i64) -> Vec<(i64, i64)> {
+    let (dx, dy) = ((x1 - x0).abs(), -(y1 - y0).abs());
+    let (step_x, step_y) = ((x1 - x0).signum(), (y1 - y0).signum());
+    let mut cells = Vec::with_capacity(dx.max(-dy) as usize + 1);
+    let (mut x, mut y) = (x0, y0);
+    let mut error = dx + dy;
+    loop {
+        cells.push((x, y));
+        if x == x1 && y == y1 {
+            return cells;
+        }
+        let doubled = 2 * error;
+        if doubled >= dy {
+            error += dy;
+            x += step_x;
+        }
+        if doubled <= dx {
+            error += dx;
+            y += step_y;
+        }
+    }
+}
+
+// Rects are (x, y, w, h) and half-open: the left and top edges are inside,
+// the right and bottom edges are not, so adjacent rects never overlap.
+fn rect_contains_point(rect: (i64, i64, i64, i64), px: i64, py: i64) -> bool {
+    let (x, y, w, h) = rect;
+    px >= x && px < x + w && py >= y && py < y + h
+}
+
+fn rects_intersect(a: (i64, i64, i64, i64), b: (i64, i64, i64, i64)) -> bool {
+    let (ax, ay, aw, ah) = a;
+    let (bx, by, bw, bh) = b;
+    aw > 0
+        && ah > 0
+        && bw > 0
+        && bh > 0
+        && ax < bx + bw
+        && bx < ax + aw
+        && ay < by + bh
+        && by < ay + ah
+}
+
+// Pixel layouts accepted by create_streaming_texture. RGBA32 and friends are
+// byte orders (R first in memory); RGBA8888 and friends are packed 32-bit words.
+fn pixel_format_from_name(name: &str) -> Result<PixelFormat, String> {
+    let format = match name.to_ascii_uppercase().as_str() {
+        "RGBA32" => SDL_PixelFormat::RGBA32,
+        "ARGB32" => SDL_PixelFormat::ARGB32,
+        "BGRA32" => SDL_PixelFormat::BGRA32,
+        "ABGR32" => SDL_PixelFormat::ABGR32,
+        "RGBA8888" => SDL_PixelFormat::RGBA8888,
+        "ARGB8888" => SDL_PixelFormat::ARGB8888,
+        "BGRA8888" => SDL_PixelFormat::BGRA8888,
+        "ABGR8888" => SDL_PixelFormat::ABGR8888,
+        "XRGB8888" => SDL_PixelFormat::XRGB8888,
+        "RGB24" => SDL_PixelFormat::RGB24,
+        "BGR24" => SDL_PixelFormat::BGR24,
+        "RGB565" => SDL_PixelFormat::RGB565,
+        _ => return Err(format!("Unknown pixel format: {}", name)),
+    };
+    PixelFormat::try_from(format).map_err(|e| e.to_string())
+}
+
+fn rect_to_array(rect: Rect) -> Array {
+    vec![
+        Dynamic::from(rect.x() as i64),
+        Dynamic::from(rect.y() as i64),
+        Dynamic::from(rect.width() as i64),
+        Dynamic::from(rect.height() as i64),
+    ]
+}
+
+// Read an [x, y, w, h] array passed from a script
+fn rect_from_dynamic(value: &Dynamic, name: &str) -> Result<Rect, String> {
+    let values = value
+        .as_array_ref()
+        .map_err(|_| format!("Expected [x, y, w, h] for {}", name))?;
+    if values.len() != 4 {
+        return Err(format!("Expected [x, y, w, h] for {}", name));
+    }
+    let mut parts = [0i64; 4];
+    for (part, value) in parts.iter_mut().zip(values.iter()) {
+        *part = value
+            .as_int()
+            .map_err(|t| format!("Expected integer in {}, got {}", name, t))?;
+    }
+    let [x, y, w, h] = parts;
+    Ok(Rect::new(
+        x as i32,
+        y as i32,
+        w.max(0) as u32,
+        h.max(0) as u32,
+    ))
+}
+
+fn blend_mode_name(mode: BlendMode) -> &'static str {
+    match mode {
+        BlendMode::None => "none",
+        BlendMode::Blend => "blend",
+        BlendMode::Add => "add",
+        BlendMode::Mod => "mod",
+        BlendMode::Mul => "mul",
+        BlendMode::Invalid => "invalid",
+    }
+}
+
+fn blend_mode_from_name(name: &str) -> Result<BlendMode, String> {
+    match name {
+        "none" => Ok(BlendMode::None),
+        "blend" => Ok(BlendMode::Blend),
+        "add" => Ok(BlendMode::Add),
+        "mod" => Ok(BlendMode::Mod),
+        "mul" => Ok(BlendMode::Mul),
+        _ => Err(format!("Unknown blend mode: {}", name)),
+    }
+}
+
+// Read an [r, g, b] array passed from a script
+fn color_from_array(rgb: &Array) -> Result<Color, String> {
+    if rgb.len() != 3 {
+        return Err(format!("Expected [r, g, b], got {} elements", rgb.len()));
+    }
+    let mut channels = [0u8; 3];
+    for (channel, value) in channels.iter_mut().zip(rgb) {
+        *channel = value
+            .as_int()
+            .map_err(|t| format!("Expected integer color channel, got {}", t))?
+            as u8;
+    }
+    Ok(Color::RGB(channels[0], channels[1], channels[2]))
+}
+
+// Fill a polygon with the current draw color using an even-odd scanline fill
+fn scanline_fill(canvas: &mut Canvas<Window>, points: &[(f32, f32)]) -> Result<(), String> {
+    canvas
+        .fill_rects(&scanline_spans(points))
+        .map_err(|e| e.to_string())
+}
+
+// One-pixel-high spans covering a polygon by the even-odd rule, sampling each
+// pixel row at its center
+fn scanline_spans(points: &[(f32, f32)]) -> Vec<FRect> {
+    let min_y = points.iter().map(|p| p.1).fold(f32::INFINITY, f32::min);
+    let max_y = points.iter().map(|p| p.1).fold(f32::NEG_INFINITY, f32::max);
+    let mut spans = Vec::new();
+    let mut crossings = Vec::new();
+    for row in min_y.floor() as i32..=max_y.ceil() as i32 {
+        let y = row as f32 + 0.5;
+        crossings.clear();
+        for (i, &(x1, y1)) in points.iter().enumerate() {
+            let (x2, y2) = points[(i + 1) % points.len()];
+            if (y1 <= y) != (y2 <= y) {
+                crossings.push(x1 + (y - y1) / (y2 - y1) * (x2 - x1));
+            }
+        }
+        crossings.sort_by(f32::total_cmp);
+        for pair in crossings.chunks_exact(2) {
+            let (start, end) = (pair[0].round(), pair[1].round());
+            if end > start {
+                spans.push(FRect::new(start, row as f32, end - start, 1.0));
+            }
+        }
+    }
+    spans
+}
+
+// Convert HSV (hue in degrees, wrapping at 360; saturation and value in
+// [0, 1]) to 8-bit RGB
+fn hsv_to_rgb(h: f64, s: f64, v: f64) -> (u8, u8, u8) {
+    let h = h.rem_euclid(360.0);
+    let s = s.clamp(0.0, 1.0);
+    let v = v.clamp(0.0, 1.0);
+    let c = v * s;
+    let x = c * (1.0 - ((h / 60.0) % 2.0 - 1.0).abs());
+    let m = v - c;
+    let (r, g, b) = match (h / 60.0) as u32 {
+        0 => (c, x, 0.0),
+        1 => (x, c, 0.0),
+        2 => (0.0, c, x),
+        3 => (0.0, x, c),
+        4 => (x, 0.0, c),
+        _ => (c, 0.0, x),
+    };
+    let channel = |value: f64| ((value + m) * 255.0).round() as u8;
+    (channel(r), channel(g), channel(b))
+}
+
+// Convert 8-bit RGB to HSV, the inverse of hsv_to_rgb
+fn rgb_to_hsv(r: u8, g: u8, b: u8) -> (f64, f64, f64) {
+    let (r, g, b) = (r as f64 / 255.0, g as f64 / 255.0, b as f64 / 255.0);
+    let max = r.max(g).max(b);
+    let min = r.min(g).min(b);
+    let delta = max - min;
+    let h = if delta == 0.0 {
+        0.0
+    } else if max == r {
+        60.0 * ((g - b) / delta).rem_euclid(6.0)
+    } else if max == g {
+        60.0 * ((b - r) / delta + 2.0)
+    } else {
+        60.0 * ((r - g) / delta + 4.0)
+    };
+    let s = if max == 0.0 { 0.0 } else { delta / max };
+    (h, s, max)
+}
+
+// Evaluate a Bézier curve of any degree at t using De Casteljau's algorithm
+fn bezier_point(points: &[(f32, f32)], t: f32) -> (f32, f32) {
+    let mut work = points.to_vec();
+    for n in (1..work.len()).rev() {
+        for i in 0..n {
+            work[i] = (
+                work[i].0 + (work[i + 1].0 - work[i].0) * t,
+                work[i].1 + (work[i + 1].1 - work[i].1) * t,
+            );
+        }
+    }
+    work[0]
+}
+
+// Script-facing mouse button name, matching is_mouse_button_down
+fn mouse_button_name(button: MouseButton) -> &'static str {
+    match button {
+        MouseButton::Left => "left",
+        MouseButton::Middle => "middle",
+        MouseButton::Right => "right",
+        MouseButton::X1 => "x1",
+        MouseButton::X2 => "x2",
+        MouseButton::Unknown => "unknown",
+    }
+}
+
+// World-to-screen mapping applied by the camera-aware draws: draw_rect,
+// fill_rect, fill_rect_masked, draw_point, draw_colored_points, draw_line,
+// draw_thick_line, draw_dashed_line, draw_triangle, fill_triangle, draw_circle,
+// fill_circle, draw_bezier, draw_texture, draw_texture_scaled,
+// draw_texture_fit, draw_nine_patch, draw_sprite_batch, draw_geometry,
+// plot_pixels and draw_sorted (and draw_batch through them). Thickness and dash
+// lengths are world units, so they scale with the zoom. These also respect
+// set_origin. Other draws work in screen coordinates.
+#[derive(Clone, Copy)]
+struct Transform {
+    offset_x: f32,
+    offset_y: f32,
+    scale: f32,
+}
+
+impl Transform {
+    const IDENTITY: Transform = Transform {
+        offset_x: 0.0,
+        offset_y: 0.0,
+        scale: 1.0,
+    };
+
+    fn point(&self, x: f32, y: f32) -> FPoint {
+        FPoint::new(
+            x * self.scale + self.offset_x,
+            y * self.scale + self.offset_y,
+        )
+    }
+
+    fn rect(&self, rect: FRect) -> FRect {
+        let origin = self.point(rect.x, rect.y);
+        FRect::new(origin.x, origin.y, rect.w * self.scale, rect.h * self.scale)
+    }
+}
+
+// Draw operations since the last present, by kind. Batched draws count each
+// item: every particle is a rect, every draw_sorted sprite a texture. Outlined
+// triangles and curves count as lines, filled triangles as rects. Only draws
+// that succeed are counted.
+#[derive(Default)]
+struct DrawStats {
+    rects: u64,
+    lines: u64,
+    points: u64,
+    textures: u64,
+    text: u64,
+}
+
+impl DrawStats {
+    fn to_map(&self) -> Map {
+        let mut map = Map::new();
+        map.insert("rects".into(), Dynamic::from(self.rects as i64));
+        map.insert("lines".into(), Dynamic::from(self.lines as i64));
+        map.insert("points".into(), Dynamic::from(self.points as i64));
+        map.insert("textures".into(), Dynamic::from(self.textures as i64));
+        map.insert("text".into(), Dynamic::from(self.text as i64));
+        map
+    }
+}
+
+// RGBA32 pixels decoded off the main thread by load_texture_async
+struct DecodedImage {
+    pixels: Vec<u8>,
+    width: u32,
+    height: u32,
+    pitch: u32,
+}
+
+// Per-frame script budget from set_script_limits. Rhai counts operations per
+// evaluation, so the count at the first progress report of each frame is
+// taken as that frame's baseline, and a lower count means a new evaluation.
+// The progress callback runs on every operation, so everything is atomic and
+// the unlimited case costs two loads.
+struct ScriptLimits {
+    // 0 means no limit for both
+    max_ops: AtomicU64,
+    max_time_nanos: AtomicU64,
+    epoch: Instant,
+    // Nanoseconds after epoch at which the current frame or evaluation began
+    frame_start_nanos: AtomicU64,
+    // NO_BASELINE until the first progress report of a frame
+    ops_at_frame_start: AtomicU64,
+}
+
+impl ScriptLimits {
+    const NO_BASELINE: u64 = u64::MAX;
+    // Reading the clock on every operation is measurable, so the time budget
+    // is checked once per this many operations
+    const TIME_CHECK_INTERVAL: u64 = 256;
+
+    fn new() -> Self {
+        ScriptLimits {
+            max_ops: AtomicU64::new(0),
+            max_time_nanos: AtomicU64::new(0),
+            epoch: Instant::now(),
+            frame_start_nanos: AtomicU64::new(0),
+            ops_at_frame_start: AtomicU64::new(Self::NO_BASELINE),
+        }
+    }
+
+    fn set(&self, max_ops: u64, max_time: Option<Duration>) {
+        let nanos = max_time.map_or(0, |time| time.as_nanos().clamp(1, u64::MAX as u128) as u64);
+        self.max_ops.store(max_ops, Ordering::Relaxed);
+        self.max_time_nanos.store(nanos, Ordering::Relaxed);
+        self.start_frame();
+    }
+
+    fn now_nanos(&self) -> u64 {
+        self.epoch.elapsed().as_nanos() as u64
+    }
+
+    fn start_frame(&self) {
+        self.frame_start_nanos
+            .store(self.now_nanos(), Ordering::Relaxed);
+        self.ops_at_frame_start
+            .store(Self::NO_BASELINE, Ordering::Relaxed);
+    }
+
+    // Error message once the current frame is over budget
+    fn check(&self, ops: u64) -> Option<String> {
+        let max_ops = self.max_ops.load(Ordering::Relaxed);
+        let max_time_nanos = self.max_time_nanos.load(Ordering::Relaxed);
+        if max_ops == 0 && max_time_nanos == 0 {
+            return None;
+        }
+        let mut base = self.ops_at_frame_start.load(Ordering::Relaxed);
+        if base > ops {
+            // A host may run several evals without presenting, and each one
+            // gets the full time budget
+            if base != Self::NO_BASELINE {
+                self.frame_start_nanos
+                    .store(self.now_nanos(), Ordering::Relaxed);
+            }
+            self.ops_at_frame_start.store(ops, Ordering::Relaxed);
+            base = ops;
+        }
+        if max_ops > 0 && ops - base > max_ops {
+            return Some(format!(
+                "Script exceeded {} operations in one frame",
+                max_ops
+            ));
+        }
+        if max_time_nanos > 0
+            && (ops - base).is_multiple_of(Self::TIME_CHECK_INTERVAL)
+            && self
+                .now_nanos()
+                .saturating_sub(self.frame_start_nanos.load(Ordering::Relaxed))
+                > max_time_nanos
+        {
+            return Some(format!(
+                "Script ran for over {:.3} seconds without presenting a frame",
+                Duration::from_nanos(max_time_nanos).as_secs_f64()
+            ));
+        }
+        None
+    }
+}
+
+// Callbacks of a scene registered with register_scene
+struct Scene {
+    init: FnPtr,
+    update: FnPtr,
+    draw: FnPtr,
+}
+
+// Particle simulated by update_particles, fading out over its lifetime
+struct Particle {
+    x: f32,
+    y: f32,
+    vx: f32,
+    vy: f32,
+    gravity: f32,
+    age: f32,
+    lifetime: f32,
+    color: Color,
+}
+
+// Broad-phase spatial hash from grid_new: each id is bucketed into every
+// cell its bounding box touches, so queries only look at nearby buckets
+struct SpatialGrid {
+    cell_size: f64,
+    cells: HashMap<(i64, i64), Vec<i64>>,
+}
+
+impl SpatialGrid {
+    // Cells covered by the box, inclusive on both ends
+    fn cell_range(&self, x: f64, y: f64, w: f64, h: f64) -> ((i64, i64), (i64, i64)) {
+        let cell = |v: f64| (v / self.cell_size).floor() as i64;
+        (
+            (cell(x), cell(y)),
+            (cell(x + w.max(0.0)), cell(y + h.max(0.0))),
+        )
+    }
+
+    fn insert(&mut self, id: i64, x: f64, y: f64, w: f64, h: f64) {
+        let ((x0, y0), (x1, y1)) = self.cell_range(x, y, w, h);
+        for cy in y0..=y1 {
+            for cx in x0..=x1 {
+                self.cells.entry((cx, cy)).or_default().push(id);
+            }
+        }
+    }
+
+    // Ids sharing a cell with the box, each once, in first-seen order
+    fn query(&self, x: f64, y: f64, w: f64, h: f64) -> Array {
+        let ((x0, y0), (x1, y1)) = self.cell_range(x, y, w, h);
+        let mut seen = HashSet::new();
+        let mut ids = Array::new();
+        for cy in y0..=y1 {
+            for cx in x0..=x1 {
+                for &id in self.cells.get(&(cx, cy)).into_iter().flatten() {
+                    if seen.insert(id) {
+                        ids.push(Dynamic::from(id));
+                    }
+                }
+            }
+        }
+        ids
+    }
+}
+
+// Read an optional number from a script config map, accepting ints or floats
+fn map_number(map: &Map, key: &str, default: f64) -> Result<f64, String> {
+    match map.get(key) {
+        None => Ok(default),
+        Some(value) => value
+            .as_float()
+            .or_else(|_| value.as_int().map(|v| v as f64))
+            .map_err(|t| format!("Expected number for {}, got {}", key, t)),
+    }
+}
+
+// Read a required integer from a script map. Values outside i32 are an
+// error rather than wrapping around to some other coordinate.
+fn map_int(map: &Map, key: &str) -> Result<i32, String> {
+    let value = map_handle(map, key)?;
+    i32::try_from(value).map_err(|_| format!("{} out of range: {}", key, value))
+}
+
+// Read a required handle from a script map. Handles stay i64 all the way to
+// the lookup, so a bogus one is reported as invalid instead of truncated.
+fn map_handle(map: &Map, key: &str) -> Result<i64, String> {
+    map.get(key)
+        .ok_or_else(|| format!("Missing {}", key))?
+        .as_int()
+        .map_err(|t| format!("Expected integer for {}, got {}", key, t))
+}
+
+struct FrameCapture {
+    dir: PathBuf,
+    every_n_frames: u64,
+    frame: u64,
+    saved: u64,
+}
+
+// Save the current contents of the canvas as a PNG
+fn save_canvas(canvas: &Canvas<Window>, path: &Path) -> Result<(), String> {
+    canvas
+        .read_pixels(None)
+        .map_err(|e| e.to_string())?
+        .save(path)
+        .map_err(|e| format!("Failed to save {}: {}", path.display(), e))
+}
+
+// Recorded input is stored one event per line as the frame number, relative
+// to the start of the recording, and the event map as JSON, tab separated
+struct InputRecorder {
+    writer: BufWriter<File>,
+    start_frame: u64,
+}
+
+struct InputReplay {
+    events: VecDeque<(u64, Map)>,
+    start_frame: u64,
+}
+
+fn is_event_type(event: &Map, event_type: &str) -> bool {
+    event
+        .get("type")
+        .is_some_and(|t| t.clone().into_string().is_ok_and(|t| t == event_type))
+}
+
+// Convert an SDL event into the map returned to scripts by next_event
+fn event_to_map(event: &Event) -> Map {
+    let mut map = Map::new();
+    // When SDL generated the event, in nanoseconds since SDL was initialized
+    map.insert(
+        "timestamp".into(),
+        Dynamic::from(event.get_timestamp() as i64),
+    );
+    match event {
+        Event::Quit { .. } => {
+            map.insert("type".into(), "quit".into());
+        }
+        Event::KeyDown {
+            keycode, scancode, ..
+        }
+        | Event::KeyUp {
+            keycode, scancode, ..
+        } => {
+            let event_type = if matches!(event, Event::KeyDown { .. }) {
+                "key_down"
+            } else {
+                "key_up"
+            };
+            map.insert("type".into(), event_type.into());
+            // Physical key position, independent of the keyboard layout
+            map.insert(
+                "scancode".into(),
+                Dynamic::from(scancode.map_or(0, |s| s as i64)),
+            );
+            // Layout-dependent key, lowercase like the names accepted by is_key_down
+            let name = keycode.map(|k| k.name()).unwrap_or_default();
+            map.insert("keycode".into(), name.to_lowercase().into());
+            map.insert("key".into(), name.into());
+        }
+        Event::TextInput { text, .. } => {
+            map.insert("type".into(), "text_input".into());
+            map.insert("text".into(), text.clone().into());
+        }
+        // Coordinates are where the button changed state, not the current position.
+        // clicks counts rapid presses: 2 for a double click, 3 for a triple click.
+        Event::MouseButtonDown {
+            mouse_btn,
+            clicks,
+            x,
+            y,
+            ..
+        }
+        | Event::MouseButtonUp {
+            mouse_btn,
+            clicks,
+            x,
+            y,
+            ..
+        } => {
+            let event_type = if matches!(event, Event::MouseButtonDown { .. }) {
+                "mouse_button_down"
+            } else {
+                "mouse_button_up"
+            };
+            map.insert("type".into(), event_type.into());
+            map.insert("button".into(), mouse_button_name(*mouse_btn).into());
+            map.insert("x".into(), Dynamic::from(*x as i64));
+            map.insert("y".into(), Dynamic::from(*y as i64));
+            map.insert("clicks".into(), Dynamic::from(*clicks as i64));
+        }
+        // Touch coordinates are normalized to 0.0..1.0 across the touch device
+        Event::FingerDown {
+            finger_id,
+            x,
+            y,
+            pressure,
+            ..
+        }
+        | Event::FingerUp {
+            finger_id,
+            x,
+            y,
+            pressure,
+            ..
+        } => {
+            let event_type = if matches!(event, Event::FingerDown { .. }) {
+                "finger_down"
+            } else {
+                "finger_up"
+            };
+            map.insert("type".into(), event_type.into());
+            map.insert("finger_id".into(), Dynamic::from(*finger_id as i64));
+            map.insert("x".into(), Dynamic::from(*x as f64));
+            map.insert("y".into(), Dynamic::from(*y as f64));
+            map.insert("pressure".into(), Dynamic::from(*pressure as f64));
+        }
+        Event::FingerMotion {
+            finger_id,
+            x,
+            y,
+            dx,
+            dy,
+            pressure,
+            ..
+        } => {
+            map.insert("type".into(), "finger_motion".into());
+            map.insert("finger_id".into(), Dynamic::from(*finger_id as i64));
+            map.insert("x".into(), Dynamic::from(*x as f64));
+            map.insert("y".into(), Dynamic::from(*y as f64));
+            map.insert("dx".into(), Dynamic::from(*dx as f64));
+            map.insert("dy".into(), Dynamic::from(*dy as f64));
+            map.insert("pressure".into(), Dynamic::from(*pressure as f64));
+        }
+        // One event per dropped file
+        Event::DropFile { filename, .. } => {
+            map.insert("type".into(), "drop_file".into());
+            map.insert("path".into(), filename.clone().into());
+        }
+        Event::Window {
+            win_event: WindowEvent::FocusGained,
+            ..
+        } => {
+            map.insert("type".into(), "focus_gained".into());
+        }
+        Event::Window {
+            win_event: WindowEvent::FocusLost,
+            ..
+        } => {
+            map.insert("type".into(), "focus_lost".into());
+        }
+        _ => {
+            map.insert("type".into(), "other".into());
+        }
+    }
+    map
+}
+
+// Rhai module to register SDL functions. A panic while the context is locked
+// poisons the mutex; the guard is recovered rather than failing every later
+// call, since SDLContext holds no invariants a half-finished call can break.
+// Note: this replaces any progress callback already set on the engine with
+// the one behind set_script_limits, so a host needing its own on_progress must
+// install it afterwards and give up script limits.
+pub fn register_sdl_module(engine: &mut Engine, sdl_context: Arc<Mutex<SDLContext>>) {
+    let sdl_context_clone = sdl_context.clone();
+    engine.register_fn(
+        "create_window",
+        move |title: &str, width: i64, height: i64| -> Result<(), Box<EvalAltResult>> {
+            sdl_context_clone
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .create_window(title, width as i32, height as i32)
+                .map_err(|e| {
+                    Box::new(EvalAltResult::ErrorRuntime(
+                        Dynamic::from(e),
+                        Default::default(),
+                    ))
+                })
+        },
+    );
+
+    // Register window creation at an explicit position, pass window_pos_centered()
+    // for either coordinate to center on that axis
+    engine.register_fn("window_pos_centered", || -> i64 {
+        WINDOW_POS_CENTERED as i64
+    });
+
+    let sdl_context_clone = sdl_context.clone();
+    engine.register_fn(
+        "create_window_at",
+        move |title: &str,
+              x: i64,
+              y: i64,
+              width: i64,
+              height: i64|
+              -> Result<(), Box<EvalAltResult>> {
+            sdl_context_clone
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .create_window_at(title, x as i32, y as i32, width as i32, height as i32)
+                .map_err(|e| {
+                    Box::new(EvalAltResult::ErrorRuntime(
+                        Dynamic::from(e),
+                        Default::default(),
+                    ))
+                })
+        },
+    );
+
+    let sdl_context_clone = sdl_context.clone();
+    engine.register_fn(
+        "create_window_ex",
+        move |title: &str,
+              width: i64,
+              height: i64,
+              driver: &str|
+              -> Result<(), Box<EvalAltResult>> {
+            sdl_context_clone
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .create_window_ex(title, width as i32, height as i32, driver)
+                .map_err(|e| {
+                    Box::new(EvalAltResult::ErrorRuntime(
+                        Dynamic::from(e),
+                        Default::default(),
+                    ))
+                })
+        },
+    );
+
+    // Register video and display queries, usable before create_window
+    let sdl_context_clone = sdl_context.clone();
+    engine.register_fn("init_video", move || -> Result<(), Box<EvalAltResult>> {
+        sdl_context_clone
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .init_video()
+            .map_err(|e| {
+                Box::new(EvalAltResult::ErrorRuntime(
+                    Dynamic::from(e),
+                    Default::default(),
+                ))
+            })
+    });
+
+    let sdl_context_clone = sdl_context.clone();
+    engine.register_fn(
+        "get_num_displays",
+        move || -> Result<i64, Box<EvalAltResult>> {
+            sdl_context_clone
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .get_num_displays()
+                .map_err(|e| {
+                    Box::new(EvalAltResult::ErrorRuntime(
+                        Dynamic::from(e),
+                        Default::default(),
+                    ))
+                })
+        },
+    );
+
+    let sdl_context_clone = sdl_context.clone();
+    engine.register_fn(
+        "get_display_bounds",
+        move |index: i64| -> Result<Array, Box<EvalAltResult>> {
+            sdl_context_clone
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .get_display_bounds(index)
+                .map_err(|e| {
+                    Box::new(EvalAltResult::ErrorRuntime(
+                        Dynamic::from(e),
+                        Default::default(),
+                    ))
+                })
+        },
+    );
+
+    let sdl_context_clone = sdl_context.clone();
+    engine.register_fn(
+        "set_window_min_size",
+        move |w: i64, h: i64| -> Result<(), Box<EvalAltResult>> {
+            sdl_context_clone
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .set_window_min_size(w as u32, h as u32)
+                .map_err(|e| {
+                    Box::new(EvalAltResult::ErrorRuntime(
+                        Dynamic::from(e),
+                        Default::default(),
+                    ))
+                })
+        },
+    );
+
+    let sdl_context_clone = sdl_context.clone();
+    engine.register_fn(
+        "set_window_max_size",
+        move |w: i64, h: i64| -> Result<(), Box<EvalAltResult>> {
+            sdl_context_clone
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .set_window_max_size(w as u32, h as u32)
+                .map_err(|e| {
+                    Box::new(EvalAltResult::ErrorRuntime(
+                        Dynamic::from(e),
+                        Default::default(),
+                    ))
+                })
+        },
+    );
+
+    let sdl_context_clone = sdl_context.clone();
+    engine.register_fn(
+        "has_keyboard_focus",
+        move || -> Result<bool, Box<EvalAltResult>> {
+            sdl_context_clone
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .has_keyboard_focus()
+                .map_err(|e| {
+                    Box::new(EvalAltResult::ErrorRuntime(
+                        Dynamic::from(e),
+                        Default::default(),
+                    ))
+                })
+        },
+    );
+
+    let sdl_context_clone = sdl_context.clone();
+    engine.register_fn(
+        "set_always_on_top",
+        move |on_top: bool| -> Result<(), Box<EvalAltResult>> {
+            sdl_context_clone
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .set_always_on_top(on_top)
+                .map_err(|e| {
+                    Box::new(EvalAltResult::ErrorRuntime(
+                        Dynamic::from(e),
+                        Default::default(),
+                    ))
+                })
+        },
+    );
+
+    let sdl_context_clone = sdl_context.clone();
+    engine.register_fn(
+        "minimize_window",
+        move || -> Result<(), Box<EvalAltResult>> {
+            sdl_context_clone
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .minimize_window()
+                .map_err(|e| {
+                    Box::new(EvalAltResult::ErrorRuntime(
+                        Dynamic::from(e),
+                        Default::default(),
+                    ))
+                })
+        },
+    );
+
+    let sdl_context_clone = sdl_context.clone();
+    engine.register_fn(
+        "maximize_window",
+        move || -> Result<(), Box<EvalAltResult>> {
+            sdl_context_clone
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .maximize_window()
+                .map_err(|e| {
+                    Box::new(EvalAltResult::ErrorRuntime(
+                        Dynamic::from(e),
+                        Default::default(),
+                    ))
+                })
+        },
+    );
+
+    let sdl_context_clone = sdl_context.clone();
+    engine.register_fn(
+        "restore_window",
+        move || -> Result<(), Box<EvalAltResult>> {
+            sdl_context_clone
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .restore_window()
+                .map_err(|e| {
+                    Box::new(EvalAltResult::ErrorRuntime(
+                        Dynamic::from(e),
+                        Default::default(),
+                    ))
+                })
+        },
+    );
+
+    let sdl_context_clone = sdl_context.clone();
+    engine.register_fn(
+        "set_mouse_rect",
+        move |x: i64, y: i64, w: i64, h: i64| -> Result<(), Box<EvalAltResult>> {
+            sdl_context_clone
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .set_mouse_rect(x as i32, y as i32, w as i32, h as i32)
+                .map_err(|e| {
+                    Box::new(EvalAltResult::ErrorRuntime(
+                        Dynamic::from(e),
+                        Default::default(),
+                    ))
+                })
+        },
+    );
+
+    let sdl_context_clone = sdl_context.clone();
+    engine.register_fn(
+        "clear_mouse_rect",
+        move || -> Result<(), Box<EvalAltResult>> {
+            sdl_context_clone
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .clear_mouse_rect()
+                .map_err(|e| {
+                    Box::new(EvalAltResult::ErrorRuntime(
+                        Dynamic::from(e),
+                        Default::default(),
+                    ))
+                })
+        },
+    );
+
+    let sdl_context_clone = sdl_context.clone();
+    engine.register_fn(
+        "set_custom_cursor",
+        move |path: &str, hot_x: i64, hot_y: i64| -> Result<(), Box<EvalAltResult>> {
+            sdl_context_clone
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .set_custom_cursor(path, hot_x as i32, hot_y as i32)
+                .map_err(|e| {
+                    Box::new(EvalAltResult::ErrorRuntime(
+                        Dynamic::from(e),
+                        Default::default(),
+                    ))
+                })
+        },
+    );
+
+    let sdl_context_clone = sdl_context.clone();
+    engine.register_fn(
+        "start_text_input",
+        move || -> Result<(), Box<EvalAltResult>> {
+            sdl_context_clone
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .start_text_input()
+                .map_err(|e| {
+                    Box::new(EvalAltResult::ErrorRuntime(
+                        Dynamic::from(e),
+                        Default::default(),
+                    ))
+                })
+        },
+    );
+
+    let sdl_context_clone = sdl_context.clone();
+    engine.register_fn(
+        "stop_text_input",
+        move || -> Result<(), Box<EvalAltResult>> {
+            sdl_context_clone
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .stop_text_input()
+                .map_err(|e| {
+                    Box::new(EvalAltResult::ErrorRuntime(
+                        Dynamic::from(e),
+                        Default::default(),
+                    ))
+                })
+        },
+    );
+
+    let sdl_context_clone = sdl_context.clone();
+    engine.register_fn(
+        "set_text_input_area",
+        move |x: i64, y: i64, w: i64, h: i64| -> Result<(), Box<EvalAltResult>> {
+            sdl_context_clone
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .set_text_input_area(x as i32, y as i32, w as i32, h as i32)
+                .map_err(|e| {
+                    Box::new(EvalAltResult::ErrorRuntime(
+                        Dynamic::from(e),
+                        Default::default(),
+                    ))
+                })
+        },
+    );
+
+    let sdl_context_clone = sdl_context.clone();
+    engine.register_fn(
+        "get_window_flags",
+        move || -> Result<Map, Box<EvalAltResult>> {
+            sdl_context_clone
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .get_window_flags()
+                .map_err(|e| {
+                    Box::new(EvalAltResult::ErrorRuntime(
+                        Dynamic::from(e),
+                        Default::default(),
+                    ))
+                })
+        },
+    );
+
+    let sdl_context_clone = sdl_context.clone();
+    engine.register_fn(
+        "set_fullscreen",
+        move |fullscreen: bool| -> Result<(), Box<EvalAltResult>> {
+            sdl_context_clone
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .set_fullscreen(fullscreen)
+                .map_err(|e| {
+                    Box::new(EvalAltResult::ErrorRuntime(
+                        Dynamic::from(e),
+                        Default::default(),
+                    ))
+                })
+        },
+    );
+
+    let sdl_context_clone = sdl_context.clone();
+    engine.register_fn(
+        "is_fullscreen",
+        move || -> Result<bool, Box<EvalAltResult>> {
+            sdl_context_clone
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .is_fullscreen()
+                .map_err(|e| {
+                    Box::new(EvalAltResult::ErrorRuntime(
+                        Dynamic::from(e),
+                        Default::default(),
+                    ))
+                })
+        },
+    );
+
+    // Register window size queries: get_window_size() is in points (what mouse
+    // coordinates use), get_window_pixel_size() in drawable pixels
+    let sdl_context_clone = sdl_context.clone();
+    engine.register_fn(
+        "get_window_size",
+        move || -> Result<Array, Box<EvalAltResult>> {
+            sdl_context_clone
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .get_window_size()
+                .map_err(|e| {
+                    Box::new(EvalAltResult::ErrorRuntime(
+                        Dynamic::from(e),
+                        Default::default(),
+                    ))
+                })
+        },
+    );
+
+    let sdl_context_clone = sdl_context.clone();
+    engine.register_fn(
+        "get_window_pixel_size",
+        move || -> Result<Array, Box<EvalAltResult>> {
+            sdl_context_clone
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .get_window_pixel_size()
+                .map_err(|e| {
+                    Box::new(EvalAltResult::ErrorRuntime(
+                        Dynamic::from(e),
+                        Default::default(),
+                    ))
+                })
+        },
+    );
+
+    let sdl_context_clone = sdl_context.clone();
+    engine.register_fn(
+        "get_safe_area",
+        move || -> Result<Array, Box<EvalAltResult>> {
+            sdl_context_clone
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .get_safe_area()
+                .map_err(|e| {
+                    Box::new(EvalAltResult::ErrorRuntime(
+                        Dynamic::from(e),
+                        Default::default(),
+                    ))
+                })
+        },
+    );
+
+    let sdl_context_clone = sdl_context.clone();
+    engine.register_fn(
+        "get_system_theme",
+        move || -> Result<String, Box<EvalAltResult>> {
+            sdl_context_clone
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .get_system_theme()
+                .map_err(|e| {
+                    Box::new(EvalAltResult::ErrorRuntime(
+                        Dynamic::from(e),
+                        Default::default(),
+                    ))
+                })
+        },
+    );
+
+    let sdl_context_clone = sdl_context.clone();
+    engine.register_fn(
+        "show_message_box",
+        move |title: &str, message: &str, kind: &str| -> Result<(), Box<EvalAltResult>> {
+            sdl_context_clone
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .show_message_box(title, message, kind)
+                .map_err(|e| {
+                    Box::new(EvalAltResult::ErrorRuntime(
+                        Dynamic::from(e),
+                        Default::default(),
+                    ))
+                })
+        },
+    );
+
+    let sdl_context_clone = sdl_context.clone();
+    engine.register_fn(
+        "set_draw_color",
+        move |r: i64, g: i64, b: i64| -> Result<(), Box<EvalAltResult>> {
+            sdl_context_clone
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .set_draw_color(Color::RGB(r as u8, g as u8, b as u8))
+                .map_err(|e| {
+                    Box::new(EvalAltResult::ErrorRuntime(
+                        Dynamic::from(e),
+                        Default::default(),
+                    ))
+                })
+        },
+    );
+
+    // Color overload, e.g. set_draw_color(hsv_to_rgb_color(h, 1.0, 1.0))
+    let sdl_context_clone = sdl_context.clone();
+    engine.register_fn(
+        "set_draw_color",
+        move |color: Color| -> Result<(), Box<EvalAltResult>> {
+            sdl_context_clone
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .set_draw_color(color)
+                .map_err(|e| {
+                    Box::new(EvalAltResult::ErrorRuntime(
+                        Dynamic::from(e),
+                        Default::default(),
+                    ))
+                })
+        },
+    );
+
+    // Register draw color query, returns [r, g, b, a]
+    let sdl_context_clone = sdl_context.clone();
+    engine.register_fn(
+        "get_draw_color",
+        move || -> Result<Array, Box<EvalAltResult>> {
+            sdl_context_clone
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .get_draw_color()
+                .map_err(|e| {
+                    Box::new(EvalAltResult::ErrorRuntime(
+                        Dynamic::from(e),
+                        Default::default(),
+                    ))
+                })
+        },
+    );
+
+    let sdl_context_clone = sdl_context.clone();
+    engine.register_fn("clear", move || -> Result<(), Box<EvalAltResult>> {
+        sdl_context_clone
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clear()
+            .map_err(|e| {
+                Box::new(EvalAltResult::ErrorRuntime(
+                    Dynamic::from(e),
+                    Default::default(),
+                ))
+            })
+    });
+
+    let sdl_context_clone = sdl_context.clone();
+    engine.register_fn(
+        "draw_rect",
+        move |x: i64, y: i64, w: i64, h: i64| -> Result<(), Box<EvalAltResult>> {
+            sdl_context_clone
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .draw_rect(x as i32, y as i32, w as i32, h as i32)
+                .map_err(|e| {
+                    Box::new(EvalAltResult::ErrorRuntime(
+                        Dynamic::from(e),
+                        Default::default(),
+                    ))
+                })
+        },
+    );
+
+    let sdl_context_clone = sdl_context.clone();
+    engine.register_fn(
+        "fill_rect",
+        move |x: i64, y: i64, w: i64, h: i64| -> Result<(), Box<EvalAltResult>> {
+            sdl_context_clone
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .fill_rect(x as i32, y as i32, w as i32, h as i32)
+                .map_err(|e| {
+                    Box::new(EvalAltResult::ErrorRuntime(
+                        Dynamic::from(e),
+                        Default::default(),
+                    ))
+                })
+        },
+    );
+
+    let sdl_context_clone = sdl_context.clone();
+    engine.register_fn(
+        "fill_rect_masked",
+        move |x: i64, y: i64, w: i64, h: i64, mask: i64| -> Result<(), Box<EvalAltResult>> {
+            sdl_context_clone
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .fill_rect_masked(x as i32, y as i32, w as i32, h as i32, mask)
+                .map_err(|e| {
+                    Box::new(EvalAltResult::ErrorRuntime(
+                        Dynamic::from(e),
+                        Default::default(),
+                    ))
+                })
+        },
+    );
+
+    let sdl_context_clone = sdl_context.clone();
+    engine.register_fn(
+        "draw_point",
+        move |x: i64, y: i64| -> Result<(), Box<EvalAltResult>> {
+            sdl_context_clone
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .draw_point(x as i32, y as i32)
+                .map_err(|e| {
+                    Box::new(EvalAltResult::ErrorRuntime(
+                        Dynamic::from(e),
+                        Default::default(),
+                    ))
+                })
+        },
+    );
+
+    let sdl_context_clone = sdl_context.clone();
+    engine.register_fn(
+        "draw_colored_points",
+        move |data: Array| -> Result<(), Box<EvalAltResult>> {
+            sdl_context_clone
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .draw_colored_points(&data)
+                .map_err(|e| {
+                    Box::new(EvalAltResult::ErrorRuntime(
+                        Dynamic::from(e),
+                        Default::default(),
+                    ))
+                })
+        },
+    );
+
+    let sdl_context_clone = sdl_context.clone();
+    engine.register_fn(
+        "draw_line",
+        move |x1: i64, y1: i64, x2: i64, y2: i64| -> Result<(), Box<EvalAltResult>> {
+            sdl_context_clone
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .draw_line(x1 as i32, y1 as i32, x2 as i32, y2 as i32)
+                .map_err(|e| {
+                    Box::new(EvalAltResult::ErrorRuntime(
+                        Dynamic::from(e),
+                        Default::default(),
+                    ))
+                })
+        },
+    );
+
+    let sdl_context_clone = sdl_context.clone();
+    engine.register_fn(
+        "draw_thick_line",
+        move |x1: i64,
+              y1: i64,
+              x2: i64,
+              y2: i64,
+              thickness: i64|
+              -> Result<(), Box<EvalAltResult>> {
+            sdl_context_clone
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .draw_thick_line(x1 as i32, y1 as i32, x2 as i32, y2 as i32, thickness as i32)
+                .map_err(|e| {
+                    Box::new(EvalAltResult::ErrorRuntime(
+                        Dynamic::from(e),
+                        Default::default(),
+                    ))
+                })
+        },
+    );
+
+    let sdl_context_clone = sdl_context.clone();
+    engine.register_fn(
+        "draw_dashed_line",
+        move |x1: i64,
+              y1: i64,
+              x2: i64,
+              y2: i64,
+              dash_len: i64,
+              gap_len: i64|
+              -> Result<(), Box<EvalAltResult>> {
+            sdl_context_clone
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .draw_dashed_line(
+                    x1 as i32,
+                    y1 as i32,
+                    x2 as i32,
+                    y2 as i32,
+                    dash_len as i32,
+                    gap_len as i32,
+                )
+                .map_err(|e| {
+                    Box::new(EvalAltResult::ErrorRuntime(
+                        Dynamic::from(e),
+                        Default::default(),
+                    ))
+                })
+        },
+    );
+
+    let sdl_context_clone = sdl_context.clone();
+    engine.register_fn(
+        "emit_particles",
+        move |x: i64, y: i64, count: i64, config: Map| -> Result<(), Box<EvalAltResult>> {
+            sdl_context_clone
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .emit_particles(x as f32, y as f32, count, &config)
+                .map_err(|e| {
+                    Box::new(EvalAltResult::ErrorRuntime(
+                        Dynamic::from(e),
+                        Default::default(),
+                    ))
+                })
+        },
+    );
+
+    let sdl_context_clone = sdl_context.clone();
+    engine.register_fn(
+        "update_particles",
+        move |dt: f64| -> Result<(), Box<EvalAltResult>> {
+            sdl_context_clone
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .update_particles(dt as f32)
+                .map_err(|e| {
+                    Box::new(EvalAltResult::ErrorRuntime(
+                        Dynamic::from(e),
+                        Default::default(),
+                    ))
+                })
+        },
+    );
+
+    let sdl_context_clone = sdl_context.clone();
+    engine.register_fn(
+        "draw_batch",
+        move |commands: Array| -> Result<(), Box<EvalAltResult>> {
+            sdl_context_clone
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .draw_batch(&commands)
+                .map_err(|e| {
+                    Box::new(EvalAltResult::ErrorRuntime(
+                        Dynamic::from(e),
+                        Default::default(),
+                    ))
+                })
+        },
+    );
+
+    let sdl_context_clone = sdl_context.clone();
+    engine.register_fn(
+        "draw_triangle",
+        move |x1: i64,
+              y1: i64,
+              x2: i64,
+              y2: i64,
+              x3: i64,
+              y3: i64|
+              -> Result<(), Box<EvalAltResult>> {
+            sdl_context_clone
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .draw_triangle([
+                    (x1 as i32, y1 as i32),
+                    (x2 as i32, y2 as i32),
+                    (x3 as i32, y3 as i32),
+                ])
+                .map_err(|e| {
+                    Box::new(EvalAltResult::ErrorRuntime(
+                        Dynamic::from(e),
+                        Default::default(),
+                    ))
+                })
+        },
+    );
+
+    let sdl_context_clone = sdl_context.clone();
+    engine.register_fn(
+        "fill_triangle",
+        move |x1: i64,
+              y1: i64,
+              x2: i64,
+              y2: i64,
+              x3: i64,
+              y3: i64|
+              -> Result<(), Box<EvalAltResult>> {
+            sdl_context_clone
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .fill_triangle([
+                    (x1 as i32, y1 as i32),
+                    (x2 as i32, y2 as i32),
+                    (x3 as i32, y3 as i32),
+                ])
+                .map_err(|e| {
+                    Box::new(EvalAltResult::ErrorRuntime(
+                        Dynamic::from(e),
+                        Default::default(),
+                    ))
+                })
+        },
+    );
+
+    let sdl_context_clone = sdl_context.clone();
+    engine.register_fn(
+        "draw_circle",
+        move |x: i64, y: i64, radius: i64| -> Result<(), Box<EvalAltResult>> {
+            sdl_context_clone
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .draw_circle(x as i32, y as i32, radius as i32)
+                .map_err(|e| {
+                    Box::new(EvalAltResult::ErrorRuntime(
+                        Dynamic::from(e),
+                        Default::default(),
+                    ))
+                })
+        },
+    );
+
+    let sdl_context_clone = sdl_context.clone();
+    engine.register_fn(
+        "fill_circle",
+        move |x: i64, y: i64, radius: i64| -> Result<(), Box<EvalAltResult>> {
+            sdl_context_clone
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .fill_circle(x as i32, y as i32, radius as i32)
+                .map_err(|e| {
+                    Box::new(EvalAltResult::ErrorRuntime(
+                        Dynamic::from(e),
+                        Default::default(),
+                    ))
+                })
+        },
+    );
+
+    let sdl_context_clone = sdl_context.clone();
+    engine.register_fn(
+        "draw_bezier",
+        move |x0: i64,
+              y0: i64,
+              x1: i64,
+              y1: i64,
+              x2: i64,
+              y2: i64,
+              x3: i64,
+              y3: i64,
+              segments: i64|
+              -> Result<(), Box<EvalAltResult>> {
+            sdl_context_clone
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .draw_bezier(
+                    &[
+                        (x0 as f32, y0 as f32),
+                        (x1 as f32, y1 as f32),
+                        (x2 as f32, y2 as f32),
+                        (x3 as f32, y3 as f32),
+                    ],
+                    segments as i32,
+                )
+                .map_err(|e| {
+                    Box::new(EvalAltResult::ErrorRuntime(
+                        Dynamic::from(e),
+                        Default::default(),
+                    ))
+                })
+        },
+    );
+
+    let sdl_context_clone = sdl_context.clone();
+    engine.register_fn(
+        "draw_quad_bezier",
+        move |x0: i64,
+              y0: i64,
+              x1: i64,
+              y1: i64,
+              x2: i64,
+              y2: i64,
+              segments: i64|
+              -> Result<(), Box<EvalAltResult>> {
+            sdl_context_clone
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .draw_bezier(
+                    &[
+                        (x0 as f32, y0 as f32),
+                        (x1 as f32, y1 as f32),
+                        (x2 as f32, y2 as f32),
+                    ],
+                    segments as i32,
+                )
+                .map_err(|e| {
+                    Box::new(EvalAltResult::ErrorRuntime(
+                        Dynamic::from(e),
+                        Default::default(),
+                    ))
+                })
+        },
+    );
+
+    let sdl_context_clone = sdl_context.clone();
+    engine.register_fn(
+        "load_bmp_texture",
+        move |path: &str| -> Result<i64, Box<EvalAltResult>> {
+            sdl_context_clone
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .load_bmp_texture(path)
+                .map_err(|e| {
+                    Box::new(EvalAltResult::ErrorRuntime(
+                        Dynamic::from(e),
+                        Default::default(),
+                    ))
+                })
+        },
+    );
+
+    let sdl_context_clone = sdl_context.clone();
+    engine.register_fn(
+        "load_texture",
+        move |path: &str| -> Result<i64, Box<EvalAltResult>> {
+            sdl_context_clone
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .load_texture(path)
+                .map_err(|e| {
+                    Box::new(EvalAltResult::ErrorRuntime(
+                        Dynamic::from(e),
+                        Default::default(),
+                    ))
+                })
+        },
+    );
+
+    // Register background texture loading, poll is_texture_ready(handle) before
+    // drawing the handle returned by load_texture_async(path)
+    let sdl_context_clone = sdl_context.clone();
+    engine.register_fn(
+        "load_texture_async",
+        move |path: &str| -> Result<i64, Box<EvalAltResult>> {
+            sdl_context_clone
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .load_texture_async(path)
+                .map_err(|e| {
+                    Box::new(EvalAltResult::ErrorRuntime(
+                        Dynamic::from(e),
+                        Default::default(),
+                    ))
+                })
+        },
+    );
+
+    let sdl_context_clone = sdl_context.clone();
+    engine.register_fn(
+        "is_texture_ready",
+        move |id: i64| -> Result<bool, Box<EvalAltResult>> {
+            sdl_context_clone
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .is_texture_ready(id)
+                .map_err(|e| {
+                    Box::new(EvalAltResult::ErrorRuntime(
+                        Dynamic::from(e),
+                        Default::default(),
+                    ))
+                })
+        },
+    );
+
+    let sdl_context_clone = sdl_context.clone();
+    engine.register_fn(
+        "draw_texture",
+        move |id: i64, x: i64, y: i64| -> Result<(), Box<EvalAltResult>> {
+            sdl_context_clone
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .draw_texture(id, x as i32, y as i32)
+                .map_err(|e| {
+                    Box::new(EvalAltResult::ErrorRuntime(
+                        Dynamic::from(e),
+                        Default::default(),
+                    ))
+                })
+        },
+    );
+
+    let sdl_context_clone = sdl_context.clone();
+    engine.register_fn(
+        "draw_texture_scaled",
+        move |id: i64, x: i64, y: i64, w: i64, h: i64| -> Result<(), Box<EvalAltResult>> {
+            sdl_context_clone
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .draw_texture_scaled(id, x as i32, y as i32, w as i32, h as i32)
+                .map_err(|e| {
+                    Box::new(EvalAltResult::ErrorRuntime(
+                        Dynamic::from(e),
+                        Default::default(),
+                    ))
+                })
+        },
+    );
+
+    let sdl_context_clone = sdl_context.clone();
+    engine.register_fn(
+        "draw_texture_fit",
+        move |id: i64, x: i64, y: i64, w: i64, h: i64| -> Result<(), Box<EvalAltResult>> {
+            sdl_context_clone
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .draw_texture_fit(id, x as i32, y as i32, w as i32, h as i32)
+                .map_err(|e| {
+                    Box::new(EvalAltResult::ErrorRuntime(
+                        Dynamic::from(e),
+                        Default::default(),
+                    ))
+                })
+        },
+    );
+
+    let sdl_context_clone = sdl_context.clone();
+    engine.register_fn(
+        "draw_sorted",
+        move |sprites: Array| -> Result<(), Box<EvalAltResult>> {
+            sdl_context_clone
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .draw_sorted(&sprites)
+                .map_err(|e| {
+                    Box::new(EvalAltResult::ErrorRuntime(
+                        Dynamic::from(e),
+                        Default::default(),
+                    ))
+                })
+        },
+    );
+
+    let sdl_context_clone = sdl_context.clone();
+    engine.register_fn(
+        "draw_sprite_batch",
+        move |id: i64, instances: Array| -> Result<(), Box<EvalAltResult>> {
+            sdl_context_clone
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .draw_sprite_batch(id, &instances)
+                .map_err(|e| {
+                    Box::new(EvalAltResult::ErrorRuntime(
+                        Dynamic::from(e),
+                        Default::default(),
+                    ))
+                })
+        },
+    );
+
+    let sdl_context_clone = sdl_context.clone();
+    engine.register_fn(
+        "draw_geometry",
+        move |id: i64, vertices: Array, indices: Array| -> Result<(), Box<EvalAltResult>> {
+            sdl_context_clone
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .draw_geometry(id, &vertices, &indices)
+                .map_err(|e| {
+                    Box::new(EvalAltResult::ErrorRuntime(
+                        Dynamic::from(e),
+                        Default::default(),
+                    ))
+                })
+        },
+    );
+
+    let sdl_context_clone = sdl_context.clone();
+    engine.register_fn(
+        "draw_nine_patch",
+        move |id: i64,
+              dx: i64,
+              dy: i64,
+              dw: i64,
+              dh: i64,
+              border: i64|
+              -> Result<(), Box<EvalAltResult>> {
+            sdl_context_clone
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .draw_nine_patch(
+                    id,
+                    dx as i32,
+                    dy as i32,
+                    dw as i32,
+                    dh as i32,
+                    border as i32,
+                )
+                .map_err(|e| {
+                    Box::new(EvalAltResult::ErrorRuntime(
+                        Dynamic::from(e),
+                        Default::default(),
+                    ))
+                })
+        },
+    );
+
+    // Register streaming textures: create_streaming_texture(w, h, "RGBA32") returns
+    // a texture handle whose pixels update_texture(handle, blob) replaces
+    let sdl_context_clone = sdl_context.clone();
+    engine.register_fn(
+        "create_streaming_texture",
+        move |w: i64, h: i64, format: &str| -> Result<i64, Box<EvalAltResult>> {
+            sdl_context_clone
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .create_streaming_texture(w as u32, h as u32, format)
+                .map_err(|e| {
+                    Box::new(EvalAltResult::ErrorRuntime(
+                        Dynamic::from(e),
+                        Default::default(),
+                    ))
+                })
+        },
+    );
+
+    let sdl_context_clone = sdl_context.clone();
+    engine.register_fn(
+        "update_texture",
+        move |id: i64, pixels: Blob| -> Result<(), Box<EvalAltResult>> {
+            sdl_context_clone
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .update_texture(id, &pixels)
+                .map_err(|e| {
+                    Box::new(EvalAltResult::ErrorRuntime(
+                        Dynamic::from(e),
+                        Default::default(),
+                    ))
+                })
+        },
+    );
+
+    // Register the pixel plotter, plot_pixels(width, data) draws at the top
+    // left and plot_pixels(x, y, width, data) at (x, y)
+    let sdl_context_clone = sdl_context.clone();
+    engine.register_fn(
+        "plot_pixels",
+        move |width: i64, data: Array| -> Result<(), Box<EvalAltResult>> {
+            sdl_context_clone
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .plot_pixels(0, 0, width, &data)
+                .map_err(|e| {
+                    Box::new(EvalAltResult::ErrorRuntime(
+                        Dynamic::from(e),
+                        Default::default(),
+                    ))
+                })
+        },
+    );
+
+    let sdl_context_clone = sdl_context.clone();
+    engine.register_fn(
+        "plot_pixels",
+        move |x: i64, y: i64, width: i64, data: Array| -> Result<(), Box<EvalAltResult>> {
+            sdl_context_clone
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .plot_pixels(x as i32, y as i32, width, &data)
+                .map_err(|e| {
+                    Box::new(EvalAltResult::ErrorRuntime(
+                        Dynamic::from(e),
+                        Default::default(),
+                    ))
+                })
+        },
+    );
+
+    // Register texture composition, e.g. assembling an atlas once with
+    // blit_texture_to_texture(src, atlas, [0, 0, 16, 16], [32, 0, 16, 16])
+    let sdl_context_clone = sdl_context.clone();
+    engine.register_fn(
+        "create_target_texture",
+        move |w: i64, h: i64| -> Result<i64, Box<EvalAltResult>> {
+            sdl_context_clone
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .create_target_texture(w as u32, h as u32)
+                .map_err(|e| {
+                    Box::new(EvalAltResult::ErrorRuntime(
+                        Dynamic::from(e),
+                        Default::default(),
+                    ))
+                })
+        },
+    );
+
+    let sdl_context_clone = sdl_context.clone();
+    engine.register_fn(
+        "blit_texture_to_texture",
+        move |src: i64,
+              dst: i64,
+              src_rect: Dynamic,
+              dst_rect: Dynamic|
+              -> Result<(), Box<EvalAltResult>> {
+            sdl_context_clone
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .blit_texture_to_texture(src, dst, &src_rect, &dst_rect)
+                .map_err(|e| {
+                    Box::new(EvalAltResult::ErrorRuntime(
+                        Dynamic::from(e),
+                        Default::default(),
+                    ))
+                })
+        },
+    );
+
+    // Register drawing layers: set_active_layer(handle) routes draws to a layer
+    // from create_layer and set_active_layer(0) back to the screen
+    let sdl_context_clone = sdl_context.clone();
+    engine.register_fn(
+        "create_layer",
+        move |w: i64, h: i64| -> Result<i64, Box<EvalAltResult>> {
+            sdl_context_clone
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .create_layer(w as u32, h as u32)
+                .map_err(|e| {
+                    Box::new(EvalAltResult::ErrorRuntime(
+                        Dynamic::from(e),
+                        Default::default(),
+                    ))
+                })
+        },
+    );
+
+    let sdl_context_clone = sdl_context.clone();
+    engine.register_fn(
+        "set_active_layer",
+        move |handle: i64| -> Result<(), Box<EvalAltResult>> {
+            sdl_context_clone
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .set_active_layer(handle)
+                .map_err(|e| {
+                    Box::new(EvalAltResult::ErrorRuntime(
+                        Dynamic::from(e),
+                        Default::default(),
+                    ))
+                })
+        },
+    );
+
+    let sdl_context_clone = sdl_context.clone();
+    engine.register_fn(
+        "resize_layer",
+        move |handle: i64, w: i64, h: i64| -> Result<(), Box<EvalAltResult>> {
+            sdl_context_clone
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .resize_layer(handle, w as u32, h as u32)
+                .map_err(|e| {
+                    Box::new(EvalAltResult::ErrorRuntime(
+                        Dynamic::from(e),
+                        Default::default(),
+                    ))
+                })
+        },
+    );
+
+    let sdl_context_clone = sdl_context.clone();
+    engine.register_fn(
+        "composite_layers",
+        move |order: Array, opacities: Array| -> Result<(), Box<EvalAltResult>> {
+            sdl_context_clone
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .composite_layers(order, opacities)
+                .map_err(|e| {
+                    Box::new(EvalAltResult::ErrorRuntime(
+                        Dynamic::from(e),
+                        Default::default(),
+                    ))
+                })
+        },
+    );
+
+    let sdl_context_clone = sdl_context.clone();
+    engine.register_fn(
+        "load_font",
+        move |path: &str, point_size: i64| -> Result<i64, Box<EvalAltResult>> {
+            sdl_context_clone
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .load_font(path, point_size as f32)
+                .map_err(|e| {
+                    Box::new(EvalAltResult::ErrorRuntime(
+                        Dynamic::from(e),
+                        Default::default(),
+                    ))
+                })
+        },
+    );
+
+    let sdl_context_clone = sdl_context.clone();
+    engine.register_fn(
+        "draw_text",
+        move |font: i64, text: &str, x: i64, y: i64| -> Result<(), Box<EvalAltResult>> {
+            sdl_context_clone
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .draw_text(font, text, x as i32, y as i32)
+                .map_err(|e| {
+                    Box::new(EvalAltResult::ErrorRuntime(
+                        Dynamic::from(e),
+                        Default::default(),
+                    ))
+                })
+        },
+    );
+
+    let sdl_context_clone = sdl_context.clone();
+    engine.register_fn(
+        "draw_text_rotated",
+        move |font: i64,
+              text: &str,
+              x: i64,
+              y: i64,
+              angle: f64|
+              -> Result<(), Box<EvalAltResult>> {
+            sdl_context_clone
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .draw_text_rotated(font, text, x as i32, y as i32, angle)
+                .map_err(|e| {
+                    Box::new(EvalAltResult::ErrorRuntime(
+                        Dynamic::from(e),
+                        Default::default(),
+                    ))
+                })
+        },
+    );
+
+    let sdl_context_clone = sdl_context.clone();
+    engine.register_fn(
+        "measure_text",
+        move |font: i64, text: &str| -> Result<Array, Box<EvalAltResult>> {
+            sdl_context_clone
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .measure_text(font, text)
+                .map(|(w, h)| vec![Dynamic::from(w as i64), Dynamic::from(h as i64)])
+                .map_err(|e| {
+                    Box::new(EvalAltResult::ErrorRuntime(
+                        Dynamic::from(e),
+                        Default::default(),
+                    ))
+                })
+        },
+    );
+
+    let sdl_context_clone = sdl_context.clone();
+    engine.register_fn(
+        "draw_text_bg",
+        move |font: i64,
+              text: &str,
+              x: i64,
+              y: i64,
+              text_rgb: Array,
+              bg_rgb: Array,
+              padding: i64|
+              -> Result<(), Box<EvalAltResult>> {
+            sdl_context_clone
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .draw_text_bg(
+                    font,
+                    text,
+                    x as i32,
+                    y as i32,
+                    &text_rgb,
+                    &bg_rgb,
+                    padding as i32,
+                )
+                .map_err(|e| {
+                    Box::new(EvalAltResult::ErrorRuntime(
+                        Dynamic::from(e),
+                        Default::default(),
+                    ))
+                })
+        },
+    );
+
+    let sdl_context_clone = sdl_context.clone();
+    engine.register_fn(
+        "draw_text_outlined",
+        move |font: i64,
+              text: &str,
+              x: i64,
+              y: i64,
+              text_rgb: Array,
+              outline_rgb: Array|
+              -> Result<(), Box<EvalAltResult>> {
+            sdl_context_clone
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .draw_text_outlined(font, text, x as i32, y as i32, &text_rgb, &outline_rgb)
+                .map_err(|e| {
+                    Box::new(EvalAltResult::ErrorRuntime(
+                        Dynamic::from(e),
+                        Default::default(),
+                    ))
+                })
+        },
+    );
+
+    let sdl_context_clone = sdl_context.clone();
+    engine.register_fn(
+        "draw_text_styled",
+        move |font: i64,
+              text: &str,
+              x: i64,
+              y: i64,
+              scale: f64,
+              letter_spacing: f64|
+              -> Result<(), Box<EvalAltResult>> {
+            sdl_context_clone
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .draw_text_styled(
+                    font,
+                    text,
+                    x as i32,
+                    y as i32,
+                    scale as f32,
+                    letter_spacing as f32,
+                )
+                .map_err(|e| {
+                    Box::new(EvalAltResult::ErrorRuntime(
+                        Dynamic::from(e),
+                        Default::default(),
+                    ))
+                })
+        },
+    );
+
+    let sdl_context_clone = sdl_context.clone();
+    engine.register_fn(
+        "draw_bitmap_text",
+        move |text: &str, x: i64, y: i64, scale: i64| -> Result<(), Box<EvalAltResult>> {
+            sdl_context_clone
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .draw_bitmap_text(text, x as i32, y as i32, scale as i32)
+                .map_err(|e| {
+                    Box::new(EvalAltResult::ErrorRuntime(
+                        Dynamic::from(e),
+                        Default::default(),
+                    ))
+                })
+        },
+    );
+
+    let sdl_context_clone = sdl_context.clone();
+    engine.register_fn(
+        "button",
+        move |x: i64,
+              y: i64,
+              w: i64,
+              h: i64,
+              label: &str,
+              font: i64|
+              -> Result<bool, Box<EvalAltResult>> {
+            sdl_context_clone
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .button(x as i32, y as i32, w as i32, h as i32, label, font)
+                .map_err(|e| {
+                    Box::new(EvalAltResult::ErrorRuntime(
+                        Dynamic::from(e),
+                        Default::default(),
+                    ))
+                })
+        },
+    );
+
+    let sdl_context_clone = sdl_context.clone();
+    engine.register_fn(
+        "draw_progress_bar",
+        move |x: i64,
+              y: i64,
+              w: i64,
+              h: i64,
+              fraction: f64,
+              fg_rgb: Array,
+              bg_rgb: Array|
+              -> Result<(), Box<EvalAltResult>> {
+            sdl_context_clone
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .draw_progress_bar(
+                    x as i32, y as i32, w as i32, h as i32, fraction, &fg_rgb, &bg_rgb,
+                )
+                .map_err(|e| {
+                    Box::new(EvalAltResult::ErrorRuntime(
+                        Dynamic::from(e),
+                        Default::default(),
+                    ))
+                })
+        },
+    );
+
+    let sdl_context_clone = sdl_context.clone();
+    engine.register_fn("present", move || -> Result<(), Box<EvalAltResult>> {
+        sdl_context_clone
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .present()
+            .map_err(|e| {
+                Box::new(EvalAltResult::ErrorRuntime(
+                    Dynamic::from(e),
+                    Default::default(),
+                ))
+            })
+    });
+
+    // Register the camera and transform stack. set_camera(x, y, zoom) looks at
+    // world point (x, y); push_transform(x, y, zoom) nests a local coordinate
+    // system until the matching pop_transform().
+    let sdl_context_clone = sdl_context.clone();
+    engine.register_fn(
+        "set_camera",
+        move |x: f64, y: f64, zoom: f64| -> Result<(), Box<EvalAltResult>> {
+            sdl_context_clone
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .set_camera(x as f32, y as f32, zoom as f32);
+            Ok(())
+        },
+    );
+
+    let sdl_context_clone = sdl_context.clone();
+    engine.register_fn(
+        "push_transform",
+        move |x: f64, y: f64, zoom: f64| -> Result<(), Box<EvalAltResult>> {
+            sdl_context_clone
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .push_transform(x as f32, y as f32, zoom as f32);
+            Ok(())
+        },
+    );
+
+    let sdl_context_clone = sdl_context.clone();
+    engine.register_fn(
+        "pop_transform",
+        move || -> Result<(), Box<EvalAltResult>> {
+            sdl_context_clone
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .pop_transform()
+                .map_err(|e| {
+                    Box::new(EvalAltResult::ErrorRuntime(
+                        Dynamic::from(e),
+                        Default::default(),
+                    ))
+                })
+        },
+    );
+
+    // Register the draw origin, set_origin(x, y) offsets the same draws the
+    // camera applies to until reset_origin()
+    let sdl_context_clone = sdl_context.clone();
+    engine.register_fn(
+        "set_origin",
+        move |x: f64, y: f64| -> Result<(), Box<EvalAltResult>> {
+            sdl_context_clone
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .set_origin(x as f32, y as f32);
+            Ok(())
+        },
+    );
+
+    let sdl_context_clone = sdl_context.clone();
+    engine.register_fn("reset_origin", move || -> Result<(), Box<EvalAltResult>> {
+        sdl_context_clone
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .reset_origin();
+        Ok(())
+    });
+
+    let sdl_context_clone = sdl_context.clone();
+    engine.register_fn(
+        "set_viewport",
+        move |x: i64, y: i64, w: i64, h: i64| -> Result<(), Box<EvalAltResult>> {
+            sdl_context_clone
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .set_viewport(x as i32, y as i32, w as i32, h as i32)
+                .map_err(|e| {
+                    Box::new(EvalAltResult::ErrorRuntime(
+                        Dynamic::from(e),
+                        Default::default(),
+                    ))
+                })
+        },
+    );
+
+    let sdl_context_clone = sdl_context.clone();
+    engine.register_fn(
+        "reset_viewport",
+        move || -> Result<(), Box<EvalAltResult>> {
+            sdl_context_clone
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .reset_viewport()
+                .map_err(|e| {
+                    Box::new(EvalAltResult::ErrorRuntime(
+                        Dynamic::from(e),
+                        Default::default(),
+                    ))
+                })
+        },
+    );
+
+    let sdl_context_clone = sdl_context.clone();
+    engine.register_fn("save_state", move || -> Result<Map, Box<EvalAltResult>> {
+        sdl_context_clone
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .save_state()
+            .map_err(|e| {
+                Box::new(EvalAltResult::ErrorRuntime(
+                    Dynamic::from(e),
+                    Default::default(),
+                ))
+            })
+    });
+
+    let sdl_context_clone = sdl_context.clone();
+    engine.register_fn(
+        "restore_state",
+        move |state: Map| -> Result<(), Box<EvalAltResult>> {
+            sdl_context_clone
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .restore_state(&state)
+                .map_err(|e| {
+                    Box::new(EvalAltResult::ErrorRuntime(
+                        Dynamic::from(e),
+                        Default::default(),
+                    ))
+                })
+        },
+    );
+
+    let sdl_context_clone = sdl_context.clone();
+    engine.register_fn(
+        "set_logical_presentation",
+        move |w: i64, h: i64, mode: &str| -> Result<(), Box<EvalAltResult>> {
+            sdl_context_clone
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .set_logical_presentation(w as u32, h as u32, mode)
+                .map_err(|e| {
+                    Box::new(EvalAltResult::ErrorRuntime(
+                        Dynamic::from(e),
+                        Default::default(),
+                    ))
+                })
+        },
+    );
+
+    let sdl_context_clone = sdl_context.clone();
+    engine.register_fn(
+        "set_integer_scale",
+        move |enabled: bool| -> Result<(), Box<EvalAltResult>> {
+            sdl_context_clone
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .set_integer_scale(enabled)
+                .map_err(|e| {
+                    Box::new(EvalAltResult::ErrorRuntime(
+                        Dynamic::from(e),
+                        Default::default(),
+                    ))
+                })
+        },
+    );
+
+    let sdl_context_clone = sdl_context.clone();
+    engine.register_fn(
+        "window_to_logical",
+        move |x: f64, y: f64| -> Result<Array, Box<EvalAltResult>> {
+            sdl_context_clone
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .window_to_logical(x as f32, y as f32)
+                .map_err(|e| {
+                    Box::new(EvalAltResult::ErrorRuntime(
+                        Dynamic::from(e),
+                        Default::default(),
+                    ))
+                })
+        },
+    );
+
+    let sdl_context_clone = sdl_context.clone();
+    engine.register_fn(
+        "logical_to_window",
+        move |x: f64, y: f64| -> Result<Array, Box<EvalAltResult>> {
+            sdl_context_clone
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .logical_to_window(x as f32, y as f32)
+                .map_err(|e| {
+                    Box::new(EvalAltResult::ErrorRuntime(
+                        Dynamic::from(e),
+                        Default::default(),
+                    ))
+                })
+        },
+    );
+
+    // Integer overload for the positions in mouse event maps
+    let sdl_context_clone = sdl_context.clone();
+    engine.register_fn(
+        "window_to_logical",
+        move |x: i64, y: i64| -> Result<Array, Box<EvalAltResult>> {
+            sdl_context_clone
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .window_to_logical(x as f32, y as f32)
+                .map_err(|e| {
+                    Box::new(EvalAltResult::ErrorRuntime(
+                        Dynamic::from(e),
+                        Default::default(),
+                    ))
+                })
+        },
+    );
+
+    let sdl_context_clone = sdl_context.clone();
+    engine.register_fn(
+        "set_target_fps",
+        move |fps: i64| -> Result<(), Box<EvalAltResult>> {
+            sdl_context_clone
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .set_target_fps(fps.max(0) as u32)
+                .map_err(|e| {
+                    Box::new(EvalAltResult::ErrorRuntime(
+                        Dynamic::from(e),
+                        Default::default(),
+                    ))
+                })
+        },
+    );
+
+    let sdl_context_clone = sdl_context.clone();
+    engine.register_fn(
+        "set_swap_interval",
+        move |mode: &str| -> Result<String, Box<EvalAltResult>> {
+            sdl_context_clone
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .set_swap_interval(mode)
+                .map_err(|e| {
+                    Box::new(EvalAltResult::ErrorRuntime(
+                        Dynamic::from(e),
+                        Default::default(),
+                    ))
+                })
+        },
+    );
+
+    let sdl_context_clone = sdl_context.clone();
+    engine.register_fn(
+        "save_screenshot",
+        move |path: &str| -> Result<(), Box<EvalAltResult>> {
+            sdl_context_clone
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .save_screenshot(path)
+                .map_err(|e| {
+                    Box::new(EvalAltResult::ErrorRuntime(
+                        Dynamic::from(e),
+                        Default::default(),
+                    ))
+                })
+        },
+    );
+
+    let sdl_context_clone = sdl_context.clone();
+    engine.register_fn(
+        "start_capture",
+        move |dir: &str, every_n_frames: i64| -> Result<(), Box<EvalAltResult>> {
+            sdl_context_clone
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .start_capture(dir, every_n_frames)
+                .map_err(|e| {
+                    Box::new(EvalAltResult::ErrorRuntime(
+                        Dynamic::from(e),
+                        Default::default(),
+                    ))
+                })
+        },
+    );
+
+    let sdl_context_clone = sdl_context.clone();
+    engine.register_fn("stop_capture", move || -> Result<(), Box<EvalAltResult>> {
+        sdl_context_clone
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .stop_capture()
+            .map_err(|e| {
+                Box::new(EvalAltResult::ErrorRuntime(
+                    Dynamic::from(e),
+                    Default::default(),
+                ))
+            })
+    });
+
+    let sdl_context_clone = sdl_context.clone();
+    engine.register_fn(
+        "init_event_pump",
+        move || -> Result<(), Box<EvalAltResult>> {
+            sdl_context_clone
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .init_event_pump()
+                .map_err(|e| {
+                    Box::new(EvalAltResult::ErrorRuntime(
+                        Dynamic::from(e),
+                        Default::default(),
+                    ))
+                })
+        },
+    );
+
+    let sdl_context_clone = sdl_context.clone();
+    engine.register_fn("poll_event", move || -> Result<bool, Box<EvalAltResult>> {
+        sdl_context_clone
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .poll_event()
+            .map_err(|e| {
+                Box::new(EvalAltResult::ErrorRuntime(
+                    Dynamic::from(e),
+                    Default::default(),
+                ))
+            })
+    });
+
+    let sdl_context_clone = sdl_context.clone();
+    engine.register_fn(
+        "next_event",
+        move || -> Result<Dynamic, Box<EvalAltResult>> {
+            sdl_context_clone
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .next_event()
+                .map_err(|e| {
+                    Box::new(EvalAltResult::ErrorRuntime(
+                        Dynamic::from(e),
+                        Default::default(),
+                    ))
+                })
+        },
+    );
+
+    let sdl_context_clone = sdl_context.clone();
+    engine.register_fn(
+        "poll_events_for",
+        move |max_ms: f64| -> Result<Array, Box<EvalAltResult>> {
+            sdl_context_clone
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .poll_events_for(max_ms)
+                .map_err(|e| {
+                    Box::new(EvalAltResult::ErrorRuntime(
+                        Dynamic::from(e),
+                        Default::default(),
+                    ))
+                })
+        },
+    );
+
+    let sdl_context_clone = sdl_context.clone();
+    engine.register_fn("flush_events", move || -> Result<(), Box<EvalAltResult>> {
+        sdl_context_clone
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .flush_events()
+            .map_err(|e| {
+                Box::new(EvalAltResult::ErrorRuntime(
+                    Dynamic::from(e),
+                    Default::default(),
+                ))
+            })
+    });
+
+    let sdl_context_clone = sdl_context.clone();
+    engine.register_fn(
+        "start_recording_input",
+        move |path: &str| -> Result<(), Box<EvalAltResult>> {
+            sdl_context_clone
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .start_recording_input(path)
+                .map_err(|e| {
+                    Box::new(EvalAltResult::ErrorRuntime(
+                        Dynamic::from(e),
+                        Default::default(),
+                    ))
+                })
+        },
+    );
+
+    let sdl_context_clone = sdl_context.clone();
+    engine.register_fn(
+        "stop_recording_input",
+        move || -> Result<(), Box<EvalAltResult>> {
+            sdl_context_clone
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .stop_recording_input()
+                .map_err(|e| {
+                    Box::new(EvalAltResult::ErrorRuntime(
+                        Dynamic::from(e),
+                        Default::default(),
+                    ))
+                })
+        },
+    );
+
+    let sdl_context_clone = sdl_context.clone();
+    engine.register_fn(
+        "replay_input",
+        move |path: &str| -> Result<(), Box<EvalAltResult>> {
+            sdl_context_clone
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .replay_input(path)
+                .map_err(|e| {
+                    Box::new(EvalAltResult::ErrorRuntime(
+                        Dynamic::from(e),
+                        Default::default(),
+                    ))
+                })
+        },
+    );
+
+    let sdl_context_clone = sdl_context.clone();
+    engine.register_fn(
+        "is_key_down",
+        move |key: &str| -> Result<bool, Box<EvalAltResult>> {
+            sdl_context_clone
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .is_key_down(key)
+                .map_err(|e| {
+                    Box::new(EvalAltResult::ErrorRuntime(
+                        Dynamic::from(e),
+                        Default::default(),
+                    ))
+                })
+        },
+    );
+
+    let sdl_context_clone = sdl_context.clone();
+    engine.register_fn(
+        "get_key_name_for_scancode",
+        move |code: i64| -> Result<String, Box<EvalAltResult>> {
+            sdl_context_clone
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .get_key_name_for_scancode(code)
+                .map_err(|e| {
+                    Box::new(EvalAltResult::ErrorRuntime(
+                        Dynamic::from(e),
+                        Default::default(),
+                    ))
+                })
+        },
+    );
+
+    let sdl_context_clone = sdl_context.clone();
+    engine.register_fn(
+        "is_mouse_button_down",
+        move |button: &str| -> Result<bool, Box<EvalAltResult>> {
+            sdl_context_clone
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .is_mouse_button_down(button)
+                .map_err(|e| {
+                    Box::new(EvalAltResult::ErrorRuntime(
+                        Dynamic::from(e),
+                        Default::default(),
+                    ))
+                })
+        },
+    );
+
+    let sdl_context_clone = sdl_context.clone();
+    engine.register_fn(
+        "get_pressed_mouse_buttons",
+        move || -> Result<Array, Box<EvalAltResult>> {
+            sdl_context_clone
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .get_pressed_mouse_buttons()
+                .map_err(|e| {
+                    Box::new(EvalAltResult::ErrorRuntime(
+                        Dynamic::from(e),
+                        Default::default(),
                     ))
-                })?
-                .create_window(title, width as i32, height as i32)
+                })
+        },
+    );
+
+    let sdl_context_clone = sdl_context.clone();
+    engine.register_fn(
+        "get_mouse_position",
+        move || -> Result<(i64, i64), Box<EvalAltResult>> {
+            sdl_context_clone
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .get_mouse_position()
+                .map_err(|e| {
+                    Box::new(EvalAltResult::ErrorRuntime(
+                        Dynamic::from(e),
+                        Default::default(),
+                    ))
+                })
+        },
+    );
+
+    let sdl_context_clone = sdl_context.clone();
+    engine.register_fn(
+        "set_relative_mouse_mode",
+        move |enabled: bool| -> Result<(), Box<EvalAltResult>> {
+            sdl_context_clone
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .set_relative_mouse_mode(enabled)
+                .map_err(|e| {
+                    Box::new(EvalAltResult::ErrorRuntime(
+                        Dynamic::from(e),
+                        Default::default(),
+                    ))
+                })
+        },
+    );
+
+    let sdl_context_clone = sdl_context.clone();
+    engine.register_fn(
+        "get_relative_mouse_state",
+        move || -> Result<Array, Box<EvalAltResult>> {
+            sdl_context_clone
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .get_relative_mouse_state()
+                .map_err(|e| {
+                    Box::new(EvalAltResult::ErrorRuntime(
+                        Dynamic::from(e),
+                        Default::default(),
+                    ))
+                })
+        },
+    );
+
+    let sdl_context_clone = sdl_context.clone();
+    engine.register_fn(
+        "set_relative_mouse_sensitivity",
+        move |factor: f64| -> Result<(), Box<EvalAltResult>> {
+            sdl_context_clone
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .set_relative_mouse_sensitivity(factor)
+                .map_err(|e| {
+                    Box::new(EvalAltResult::ErrorRuntime(
+                        Dynamic::from(e),
+                        Default::default(),
+                    ))
+                })
+        },
+    );
+
+    let sdl_context_clone = sdl_context.clone();
+    engine.register_fn(
+        "is_mouse_in_rect",
+        move |x: i64, y: i64, w: i64, h: i64| -> Result<bool, Box<EvalAltResult>> {
+            sdl_context_clone
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .is_mouse_in_rect(x as i32, y as i32, w as i32, h as i32)
+                .map_err(|e| {
+                    Box::new(EvalAltResult::ErrorRuntime(
+                        Dynamic::from(e),
+                        Default::default(),
+                    ))
+                })
+        },
+    );
+
+    let sdl_context_clone = sdl_context.clone();
+    engine.register_fn(
+        "list_gamepads",
+        move || -> Result<Array, Box<EvalAltResult>> {
+            sdl_context_clone
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .list_gamepads()
+                .map_err(|e| {
+                    Box::new(EvalAltResult::ErrorRuntime(
+                        Dynamic::from(e),
+                        Default::default(),
+                    ))
+                })
+        },
+    );
+
+    let sdl_context_clone = sdl_context.clone();
+    engine.register_fn(
+        "open_gamepad",
+        move |index: i64| -> Result<(), Box<EvalAltResult>> {
+            sdl_context_clone
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .open_gamepad(index)
+                .map_err(|e| {
+                    Box::new(EvalAltResult::ErrorRuntime(
+                        Dynamic::from(e),
+                        Default::default(),
+                    ))
+                })
+        },
+    );
+
+    let sdl_context_clone = sdl_context.clone();
+    engine.register_fn(
+        "gamepad_sensor",
+        move |name: &str| -> Result<Array, Box<EvalAltResult>> {
+            sdl_context_clone
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .gamepad_sensor(name)
+                .map_err(|e| {
+                    Box::new(EvalAltResult::ErrorRuntime(
+                        Dynamic::from(e),
+                        Default::default(),
+                    ))
+                })
+        },
+    );
+
+    let sdl_context_clone = sdl_context.clone();
+    engine.register_fn(
+        "gamepad_touchpad",
+        move |index: i64| -> Result<Array, Box<EvalAltResult>> {
+            sdl_context_clone
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .gamepad_touchpad(index)
+                .map_err(|e| {
+                    Box::new(EvalAltResult::ErrorRuntime(
+                        Dynamic::from(e),
+                        Default::default(),
+                    ))
+                })
+        },
+    );
+
+    // Register procedural audio: open_audio_stream(freq, channels, samples), then
+    // queue_audio([...]) with interleaved f32 samples in -1.0..1.0. A fourth
+    // argument names the output device from list_audio_devices().
+    let sdl_context_clone = sdl_context.clone();
+    engine.register_fn(
+        "open_audio_stream",
+        move |freq: i64, channels: i64, samples: i64| -> Result<(), Box<EvalAltResult>> {
+            sdl_context_clone
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .open_audio_stream(freq, channels, samples)
+                .map_err(|e| {
+                    Box::new(EvalAltResult::ErrorRuntime(
+                        Dynamic::from(e),
+                        Default::default(),
+                    ))
+                })
+        },
+    );
+
+    let sdl_context_clone = sdl_context.clone();
+    engine.register_fn(
+        "queue_audio",
+        move |samples: Array| -> Result<(), Box<EvalAltResult>> {
+            sdl_context_clone
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .queue_audio(&samples)
+                .map_err(|e| {
+                    Box::new(EvalAltResult::ErrorRuntime(
+                        Dynamic::from(e),
+                        Default::default(),
+                    ))
+                })
+        },
+    );
+
+    let sdl_context_clone = sdl_context.clone();
+    engine.register_fn(
+        "open_audio_stream",
+        move |freq: i64,
+              channels: i64,
+              samples: i64,
+              device: &str|
+              -> Result<(), Box<EvalAltResult>> {
+            sdl_context_clone
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .open_audio_stream_on(freq, channels, samples, device)
+                .map_err(|e| {
+                    Box::new(EvalAltResult::ErrorRuntime(
+                        Dynamic::from(e),
+                        Default::default(),
+                    ))
+                })
+        },
+    );
+
+    let sdl_context_clone = sdl_context.clone();
+    engine.register_fn(
+        "list_audio_devices",
+        move || -> Result<Array, Box<EvalAltResult>> {
+            sdl_context_clone
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .list_audio_devices()
+                .map_err(|e| {
+                    Box::new(EvalAltResult::ErrorRuntime(
+                        Dynamic::from(e),
+                        Default::default(),
+                    ))
+                })
+        },
+    );
+
+    let sdl_context_clone = sdl_context.clone();
+    engine.register_fn("delay", move |ms: i64| -> Result<(), Box<EvalAltResult>> {
+        sdl_context_clone
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .delay(ms as u32)
+            .map_err(|e| {
+                Box::new(EvalAltResult::ErrorRuntime(
+                    Dynamic::from(e),
+                    Default::default(),
+                ))
+            })
+    });
+
+    let sdl_context_clone = sdl_context.clone();
+    engine.register_fn(
+        "after",
+        move |ms: i64, callback: FnPtr| -> Result<(), Box<EvalAltResult>> {
+            sdl_context_clone
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .after(ms.max(0) as u64, callback)
+                .map_err(|e| {
+                    Box::new(EvalAltResult::ErrorRuntime(
+                        Dynamic::from(e),
+                        Default::default(),
+                    ))
+                })
+        },
+    );
+
+    // Rust-driven frame loop: calls frame(dt) with the elapsed seconds once per
+    // frame and presents, until the window is closed or the callback returns
+    // false. See run_frames for what happens around each frame.
+    let sdl_context_clone = sdl_context.clone();
+    engine.register_fn(
+        "run_loop",
+        move |context: NativeCallContext, frame: FnPtr| -> Result<(), Box<EvalAltResult>> {
+            run_frames(&context, &sdl_context_clone, |dt| {
+                let result = frame.call_within_context::<Dynamic>(&context, (dt,))?;
+                Ok(result.as_bool() != Ok(false))
+            })
+        },
+    );
+
+    // Register scenes: register_scene(name, init, update, draw) then
+    // goto_scene(name) and run_scenes(). Each frame run_scenes calls the active
+    // scene's update(dt) and draw(); init() runs once each time a scene is
+    // entered. Returning false from update ends the loop like run_loop.
+    let sdl_context_clone = sdl_context.clone();
+    engine.register_fn(
+        "register_scene",
+        move |name: &str,
+              init: FnPtr,
+              update: FnPtr,
+              draw: FnPtr|
+              -> Result<(), Box<EvalAltResult>> {
+            sdl_context_clone
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .register_scene(name, init, update, draw);
+            Ok(())
+        },
+    );
+
+    let sdl_context_clone = sdl_context.clone();
+    engine.register_fn(
+        "goto_scene",
+        move |name: &str| -> Result<(), Box<EvalAltResult>> {
+            sdl_context_clone
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .goto_scene(name)
+                .map_err(|e| {
+                    Box::new(EvalAltResult::ErrorRuntime(
+                        Dynamic::from(e),
+                        Default::default(),
+                    ))
+                })
+        },
+    );
+
+    let sdl_context_clone = sdl_context.clone();
+    engine.register_fn(
+        "run_scenes",
+        move |context: NativeCallContext| -> Result<(), Box<EvalAltResult>> {
+            run_frames(&context, &sdl_context_clone, |dt| {
+                let (init, update, draw) = sdl_context_clone
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .scene_frame()
+                    .map_err(|e| {
+                        Box::new(EvalAltResult::ErrorRuntime(
+                            Dynamic::from(e),
+                            Default::default(),
+                        ))
+                    })?;
+                if let Some(init) = init {
+                    let _ = init.call_within_context::<Dynamic>(&context, ())?;
+                }
+                let result = update.call_within_context::<Dynamic>(&context, (dt,))?;
+                if result.as_bool() == Ok(false) {
+                    return Ok(false);
+                }
+                let _ = draw.call_within_context::<Dynamic>(&context, ())?;
+                Ok(true)
+            })
+        },
+    );
+
+    // Register script limits, set_script_limits(max_ops, max_seconds). Going
+    // over budget stops the script with an error returned to the host instead
+    // of hanging. It is ErrorTerminated, which try/catch in the script cannot
+    // catch, so a runaway loop can't swallow it and keep going. The callback
+    // has to be installed up front, because set_script_limits only gets the
+    // engine from inside a script, and it replaces any existing one; with no
+    // limits set it returns straight away.
+    let script_limits = sdl_context
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .script_limits
+        .clone();
+    engine.on_progress(move |ops| script_limits.check(ops).map(Dynamic::from));
+
+    let sdl_context_clone = sdl_context.clone();
+    engine.register_fn(
+        "set_script_limits",
+        move |max_ops: i64, max_seconds: f64| -> Result<(), Box<EvalAltResult>> {
+            sdl_context_clone
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .set_script_limits(max_ops.max(0) as u64, max_seconds);
+            Ok(())
+        },
+    );
+
+    // Register compiled scripts: compile_script(path) parses a file once and
+    // run_compiled(handle) runs it, returning its last value. All compiled
+    // scripts share one scope, so variables they define persist across runs.
+    let sdl_context_clone = sdl_context.clone();
+    engine.register_fn(
+        "compile_script",
+        move |context: NativeCallContext, path: &str| -> Result<i64, Box<EvalAltResult>> {
+            sdl_context_clone
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .compile_script(context.engine(), path)
                 .map_err(|e| {
                     Box::new(EvalAltResult::ErrorRuntime(
                         Dynamic::from(e),
@@ -201,19 +6661,16 @@ pub fn register_sdl_module(engine: &mut Engine, sdl_context: Arc<Mutex<SDLContex
         },
     );
 
+    // Register hot reload: watch_script(path) returns a run_compiled handle and
+    // reload_changed_scripts(), called once per frame, recompiles edited files
     let sdl_context_clone = sdl_context.clone();
     engine.register_fn(
-        "set_draw_color",
-        move |r: i64, g: i64, b: i64| -> Result<(), Box<EvalAltResult>> {
+        "watch_script",
+        move |context: NativeCallContext, path: &str| -> Result<i64, Box<EvalAltResult>> {
             sdl_context_clone
                 .lock()
-                .map_err(|e| {
-                    Box::new(EvalAltResult::ErrorRuntime(
-                        Dynamic::from(e.to_string()),
-                        Default::default(),
-                    ))
-                })?
-                .set_draw_color(r as u8, g as u8, b as u8)
+                .unwrap_or_else(PoisonError::into_inner)
+                .watch_script(context.engine(), path)
                 .map_err(|e| {
                     Box::new(EvalAltResult::ErrorRuntime(
                         Dynamic::from(e),
@@ -224,59 +6681,140 @@ pub fn register_sdl_module(engine: &mut Engine, sdl_context: Arc<Mutex<SDLContex
     );
 
     let sdl_context_clone = sdl_context.clone();
-    engine.register_fn("clear", move || -> Result<(), Box<EvalAltResult>> {
-        sdl_context_clone
-            .lock()
-            .map_err(|e| {
-                Box::new(EvalAltResult::ErrorRuntime(
-                    Dynamic::from(e.to_string()),
-                    Default::default(),
-                ))
-            })?
-            .clear()
-            .map_err(|e| {
+    engine.register_fn(
+        "reload_changed_scripts",
+        move |context: NativeCallContext| -> Array {
+            sdl_context_clone
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .reload_changed_scripts(context.engine())
+        },
+    );
+
+    // The context lock is released while the script runs, since it calls back
+    // into the context
+    let sdl_context_clone = sdl_context.clone();
+    engine.register_fn(
+        "run_compiled",
+        move |context: NativeCallContext, handle: i64| -> Result<Dynamic, Box<EvalAltResult>> {
+            let lock = || {
+                sdl_context_clone
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner)
+            };
+            let (ast, mut scope) = lock().begin_compiled_run(handle).map_err(|e| {
                 Box::new(EvalAltResult::ErrorRuntime(
                     Dynamic::from(e),
                     Default::default(),
                 ))
-            })
+            })?;
+            let result = context
+                .engine()
+                .eval_ast_with_scope::<Dynamic>(&mut scope, &ast);
+            lock().end_compiled_run(scope);
+            result
+        },
+    );
+
+    // Register draw statistics, counts of each kind of draw since the last present
+    let sdl_context_clone = sdl_context.clone();
+    engine.register_fn("get_draw_stats", move || -> Map {
+        sdl_context_clone
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get_draw_stats()
     });
 
+    // Register the frame budget watchdog: set_frame_budget(ms) then
+    // get_slow_frames() for the count of frames over budget and the worst one
     let sdl_context_clone = sdl_context.clone();
     engine.register_fn(
-        "draw_rect",
-        move |x: i64, y: i64, w: i64, h: i64| -> Result<(), Box<EvalAltResult>> {
+        "set_frame_budget",
+        move |ms: f64| -> Result<(), Box<EvalAltResult>> {
             sdl_context_clone
                 .lock()
-                .map_err(|e| {
-                    Box::new(EvalAltResult::ErrorRuntime(
-                        Dynamic::from(e.to_string()),
-                        Default::default(),
-                    ))
-                })?
-                .draw_rect(x as i32, y as i32, w as i32, h as i32)
-                .map_err(|e| {
-                    Box::new(EvalAltResult::ErrorRuntime(
-                        Dynamic::from(e),
-                        Default::default(),
-                    ))
-                })
+                .unwrap_or_else(PoisonError::into_inner)
+                .set_frame_budget(ms);
+            Ok(())
         },
     );
 
+    let sdl_context_clone = sdl_context.clone();
+    engine.register_fn("get_slow_frames", move || -> Map {
+        sdl_context_clone
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get_slow_frames()
+    });
+
+    // Register run_loop clear control, set_auto_clear(false, 0, 0, 0) keeps the
+    // previous frame's contents for trail and accumulation effects
     let sdl_context_clone = sdl_context.clone();
     engine.register_fn(
-        "fill_rect",
-        move |x: i64, y: i64, w: i64, h: i64| -> Result<(), Box<EvalAltResult>> {
+        "set_auto_clear",
+        move |enabled: bool, r: i64, g: i64, b: i64| -> Result<(), Box<EvalAltResult>> {
             sdl_context_clone
                 .lock()
-                .map_err(|e| {
-                    Box::new(EvalAltResult::ErrorRuntime(
-                        Dynamic::from(e.to_string()),
-                        Default::default(),
-                    ))
-                })?
-                .fill_rect(x as i32, y as i32, w as i32, h as i32)
+                .unwrap_or_else(PoisonError::into_inner)
+                .set_auto_clear(enabled, r as u8, g as u8, b as u8);
+            Ok(())
+        },
+    );
+
+    // Benchmark entry point: calls frame(dt) n times back to back with vsync and
+    // the frame limiter off, presenting after each, and returns the elapsed
+    // seconds. The previous vsync and target fps are restored afterwards.
+    let sdl_context_clone = sdl_context.clone();
+    engine.register_fn(
+        "benchmark_frames",
+        move |context: NativeCallContext,
+              n: i64,
+              frame: FnPtr|
+              -> Result<f64, Box<EvalAltResult>> {
+            let lock = || {
+                sdl_context_clone
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner)
+            };
+            let to_error = |e: String| {
+                Box::new(EvalAltResult::ErrorRuntime(
+                    Dynamic::from(e),
+                    Default::default(),
+                ))
+            };
+
+            let saved = lock().begin_benchmark().map_err(to_error)?;
+            let frequency = sdl3::timer::performance_frequency() as f64;
+            let start = sdl3::timer::performance_counter();
+            let run = || -> Result<(), Box<EvalAltResult>> {
+                let mut last_frame = start;
+                for _ in 0..n.max(0) {
+                    lock().auto_clear_frame().map_err(to_error)?;
+                    let now = sdl3::timer::performance_counter();
+                    let dt = (now - last_frame) as f64 / frequency;
+                    last_frame = now;
+                    let _ = frame.call_within_context::<Dynamic>(&context, (dt,))?;
+                    lock().present().map_err(to_error)?;
+                }
+                Ok(())
+            };
+            let result = run();
+            let elapsed = (sdl3::timer::performance_counter() - start) as f64 / frequency;
+            lock().end_benchmark(saved);
+            result.map(|_| elapsed)
+        },
+    );
+
+    // Register the spatial hash for broad-phase collision: grid_insert boxes
+    // each frame, grid_query a region for candidate ids, grid_clear to reset
+    let sdl_context_clone = sdl_context.clone();
+    engine.register_fn(
+        "grid_new",
+        move |cell_size: f64| -> Result<i64, Box<EvalAltResult>> {
+            sdl_context_clone
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .grid_new(cell_size)
                 .map_err(|e| {
                     Box::new(EvalAltResult::ErrorRuntime(
                         Dynamic::from(e),
@@ -288,17 +6826,19 @@ pub fn register_sdl_module(engine: &mut Engine, sdl_context: Arc<Mutex<SDLContex
 
     let sdl_context_clone = sdl_context.clone();
     engine.register_fn(
-        "draw_point",
-        move |x: i64, y: i64| -> Result<(), Box<EvalAltResult>> {
+        "grid_insert",
+        move |grid: i64,
+              id: i64,
+              x: f64,
+              y: f64,
+              w: f64,
+              h: f64|
+              -> Result<(), Box<EvalAltResult>> {
             sdl_context_clone
                 .lock()
-                .map_err(|e| {
-                    Box::new(EvalAltResult::ErrorRuntime(
-                        Dynamic::from(e.to_string()),
-                        Default::default(),
-                    ))
-                })?
-                .draw_point(x as i32, y as i32)
+                .unwrap_or_else(PoisonError::into_inner)
+                .grid(grid)
+                .map(|g| g.insert(id, x, y, w, h))
                 .map_err(|e| {
                     Box::new(EvalAltResult::ErrorRuntime(
                         Dynamic::from(e),
@@ -310,17 +6850,13 @@ pub fn register_sdl_module(engine: &mut Engine, sdl_context: Arc<Mutex<SDLContex
 
     let sdl_context_clone = sdl_context.clone();
     engine.register_fn(
-        "draw_line",
-        move |x1: i64, y1: i64, x2: i64, y2: i64| -> Result<(), Box<EvalAltResult>> {
+        "grid_query",
+        move |grid: i64, x: f64, y: f64, w: f64, h: f64| -> Result<Array, Box<EvalAltResult>> {
             sdl_context_clone
                 .lock()
-                .map_err(|e| {
-                    Box::new(EvalAltResult::ErrorRuntime(
-                        Dynamic::from(e.to_string()),
-                        Default::default(),
-                    ))
-                })?
-                .draw_line(x1 as i32, y1 as i32, x2 as i32, y2 as i32)
+                .unwrap_or_else(PoisonError::into_inner)
+                .grid(grid)
+                .map(|g| g.query(x, y, w, h))
                 .map_err(|e| {
                     Box::new(EvalAltResult::ErrorRuntime(
                         Dynamic::from(e),
@@ -330,38 +6866,15 @@ pub fn register_sdl_module(engine: &mut Engine, sdl_context: Arc<Mutex<SDLContex
         },
     );
 
-    let sdl_context_clone = sdl_context.clone();
-    engine.register_fn("present", move || -> Result<(), Box<EvalAltResult>> {
-        sdl_context_clone
-            .lock()
-            .map_err(|e| {
-                Box::new(EvalAltResult::ErrorRuntime(
-                    Dynamic::from(e.to_string()),
-                    Default::default(),
-                ))
-            })?
-            .present()
-            .map_err(|e| {
-                Box::new(EvalAltResult::ErrorRuntime(
-                    Dynamic::from(e),
-                    Default::default(),
-                ))
-            })
-    });
-
     let sdl_context_clone = sdl_context.clone();
     engine.register_fn(
-        "init_event_pump",
-        move || -> Result<(), Box<EvalAltResult>> {
+        "grid_clear",
+        move |grid: i64| -> Result<(), Box<EvalAltResult>> {
             sdl_context_clone
                 .lock()
-                .map_err(|e| {
-                    Box::new(EvalAltResult::ErrorRuntime(
-                        Dynamic::from(e.to_string()),
-                        Default::default(),
-                    ))
-                })?
-                .init_event_pump()
+                .unwrap_or_else(PoisonError::into_inner)
+                .grid(grid)
+                .map(|g| g.cells.clear())
                 .map_err(|e| {
                     Box::new(EvalAltResult::ErrorRuntime(
                         Dynamic::from(e),
@@ -371,38 +6884,40 @@ pub fn register_sdl_module(engine: &mut Engine, sdl_context: Arc<Mutex<SDLContex
         },
     );
 
+    // Register syntax checking for in-app editors, see check_script
+    engine.register_fn(
+        "check_script",
+        |context: NativeCallContext, source: &str| -> Map {
+            check_script(context.engine(), source)
+        },
+    );
+
+    // Register render driver enumeration, names usable with create_window_ex
+    engine.register_fn("list_render_drivers", || -> Array {
+        sdl3::render::drivers().map(Dynamic::from).collect()
+    });
+
+    // Register context reset for restart menus, see SDLContext::reset for what
+    // survives. Call create_window and the other init functions again after.
     let sdl_context_clone = sdl_context.clone();
-    engine.register_fn("poll_event", move || -> Result<bool, Box<EvalAltResult>> {
+    engine.register_fn("reset", move || -> Result<(), Box<EvalAltResult>> {
         sdl_context_clone
             .lock()
-            .map_err(|e| {
-                Box::new(EvalAltResult::ErrorRuntime(
-                    Dynamic::from(e.to_string()),
-                    Default::default(),
-                ))
-            })?
-            .poll_event()
-            .map_err(|e| {
-                Box::new(EvalAltResult::ErrorRuntime(
-                    Dynamic::from(e),
-                    Default::default(),
-                ))
-            })
+            .unwrap_or_else(PoisonError::into_inner)
+            .reset();
+        Ok(())
     });
 
+    // Register random number generator. rand, shuffle and choose share one
+    // generator that seed_rng(seed) makes reproducible.
     let sdl_context_clone = sdl_context.clone();
     engine.register_fn(
-        "is_key_down",
-        move |key: &str| -> Result<bool, Box<EvalAltResult>> {
+        "rand",
+        move |min: i64, max: i64| -> Result<i64, Box<EvalAltResult>> {
             sdl_context_clone
                 .lock()
-                .map_err(|e| {
-                    Box::new(EvalAltResult::ErrorRuntime(
-                        Dynamic::from(e.to_string()),
-                        Default::default(),
-                    ))
-                })?
-                .is_key_down(key)
+                .unwrap_or_else(PoisonError::into_inner)
+                .rand(min, max)
                 .map_err(|e| {
                     Box::new(EvalAltResult::ErrorRuntime(
                         Dynamic::from(e),
@@ -414,39 +6929,32 @@ pub fn register_sdl_module(engine: &mut Engine, sdl_context: Arc<Mutex<SDLContex
 
     let sdl_context_clone = sdl_context.clone();
     engine.register_fn(
-        "is_mouse_button_down",
-        move |button: &str| -> Result<bool, Box<EvalAltResult>> {
+        "seed_rng",
+        move |seed: i64| -> Result<(), Box<EvalAltResult>> {
             sdl_context_clone
                 .lock()
-                .map_err(|e| {
-                    Box::new(EvalAltResult::ErrorRuntime(
-                        Dynamic::from(e.to_string()),
-                        Default::default(),
-                    ))
-                })?
-                .is_mouse_button_down(button)
-                .map_err(|e| {
-                    Box::new(EvalAltResult::ErrorRuntime(
-                        Dynamic::from(e),
-                        Default::default(),
-                    ))
-                })
+                .unwrap_or_else(PoisonError::into_inner)
+                .seed_rng(seed);
+            Ok(())
         },
     );
 
+    let sdl_context_clone = sdl_context.clone();
+    engine.register_fn("shuffle", move |items: Array| -> Array {
+        sdl_context_clone
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .shuffle(&items)
+    });
+
     let sdl_context_clone = sdl_context.clone();
     engine.register_fn(
-        "get_mouse_position",
-        move || -> Result<(i64, i64), Box<EvalAltResult>> {
+        "choose",
+        move |items: Array| -> Result<Dynamic, Box<EvalAltResult>> {
             sdl_context_clone
                 .lock()
-                .map_err(|e| {
-                    Box::new(EvalAltResult::ErrorRuntime(
-                        Dynamic::from(e.to_string()),
-                        Default::default(),
-                    ))
-                })?
-                .get_mouse_position()
+                .unwrap_or_else(PoisonError::into_inner)
+                .choose(&items)
                 .map_err(|e| {
                     Box::new(EvalAltResult::ErrorRuntime(
                         Dynamic::from(e),
@@ -456,28 +6964,341 @@ pub fn register_sdl_module(engine: &mut Engine, sdl_context: Arc<Mutex<SDLContex
         },
     );
 
-    let sdl_context_clone = sdl_context.clone();
-    engine.register_fn("delay", move |ms: i64| -> Result<(), Box<EvalAltResult>> {
-        sdl_context_clone
-            .lock()
-            .map_err(|e| {
+    // Register SDL hint configuration, e.g. set_hint("SDL_RENDER_VSYNC", "1").
+    // Hints read when the window and renderer are created (SDL_VIDEO_DRIVER,
+    // SDL_RENDER_DRIVER, SDL_RENDER_VSYNC, ...) must be set before create_window.
+    engine.register_fn(
+        "set_hint",
+        |name: &str, value: &str| -> Result<(), Box<EvalAltResult>> {
+            if sdl3::hint::set(name, value) {
+                Ok(())
+            } else {
+                Err(Box::new(EvalAltResult::ErrorRuntime(
+                    Dynamic::from(format!("Failed to set hint: {}", name)),
+                    Default::default(),
+                )))
+            }
+        },
+    );
+
+    // Register app metadata, best set before create_window so the window and
+    // audio streams pick it up
+    engine.register_fn(
+        "set_app_metadata",
+        |name: &str, version: &str, identifier: &str| -> Result<(), Box<EvalAltResult>> {
+            set_app_metadata(name, version, identifier).map_err(|e| {
                 Box::new(EvalAltResult::ErrorRuntime(
-                    Dynamic::from(e.to_string()),
+                    Dynamic::from(e),
                     Default::default(),
                 ))
-            })?
-            .delay(ms as u32)
-            .map_err(|e| {
+            })
+        },
+    );
+
+    // Register high-resolution timing. perf_seconds() is the counter converted to
+    // seconds; subtract two readings to time a section of script.
+    engine.register_fn("get_perf_counter", || -> i64 {
+        sdl3::timer::performance_counter() as i64
+    });
+
+    engine.register_fn("get_perf_frequency", || -> i64 {
+        sdl3::timer::performance_frequency() as i64
+    });
+
+    engine.register_fn("perf_seconds", || -> f64 {
+        sdl3::timer::performance_counter() as f64 / sdl3::timer::performance_frequency() as f64
+    });
+
+    // Register wall-clock time, seconds since the Unix epoch, for things tied
+    // to real time rather than frame time
+    engine.register_fn("get_unix_time", || -> i64 {
+        SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_secs() as i64)
+    });
+
+    // Register rect hit-testing helpers, see rect_contains_point for the edge rules
+    engine.register_fn(
+        "rects_intersect",
+        |ax: i64, ay: i64, aw: i64, ah: i64, bx: i64, by: i64, bw: i64, bh: i64| -> bool {
+            rects_intersect((ax, ay, aw, ah), (bx, by, bw, bh))
+        },
+    );
+
+    engine.register_fn(
+        "rect_contains_point",
+        |x: i64, y: i64, w: i64, h: i64, px: i64, py: i64| -> bool {
+            rect_contains_point((x, y, w, h), px, py)
+        },
+    );
+
+    // Register line-of-sight cells, line_cells(x0, y0, x1, y1) returns [x, y] pairs
+    engine.register_fn(
+        "line_cells",
+        |x0: i64, y0: i64, x1: i64, y1: i64| -> Array {
+            line_cells(x0, y0, x1, y1)
+                .into_iter()
+                .map(|(x, y)| Dynamic::from_array(vec![Dynamic::from(x), Dynamic::from(y)]))
+                .collect()
+        },
+    );
+
+    // Register color interpolation helper, returns [r, g, b]
+    engine.register_fn(
+        "lerp_color",
+        |r1: i64, g1: i64, b1: i64, r2: i64, g2: i64, b2: i64, t: f64| -> Array {
+            let t = t.clamp(0.0, 1.0);
+            let lerp = |a: i64, b: i64| -> Dynamic {
+                let value = a as f64 + (b - a) as f64 * t;
+                Dynamic::from(value.round().clamp(0.0, 255.0) as i64)
+            };
+            vec![lerp(r1, r2), lerp(g1, g2), lerp(b1, b2)]
+        },
+    );
+
+    // Register easing, e.g. ease("ease_out_quad", t), see ease for the names
+    engine.register_fn(
+        "ease",
+        |name: &str, t: f64| -> Result<f64, Box<EvalAltResult>> {
+            ease(name, t).map_err(|e| {
                 Box::new(EvalAltResult::ErrorRuntime(
                     Dynamic::from(e),
                     Default::default(),
                 ))
             })
+        },
+    );
+
+    // Register battery queries, e.g. lowering the frame rate on battery
+    engine.register_fn("get_power_info", || -> Result<Map, Box<EvalAltResult>> {
+        power_info().map_err(|e| {
+            Box::new(EvalAltResult::ErrorRuntime(
+                Dynamic::from(e),
+                Default::default(),
+            ))
+        })
+    });
+
+    // Register the Color type: color(r, g, b) or color(r, g, b, a), with r, g,
+    // b and a properties holding channels from 0 to 255
+    engine
+        .register_type_with_name::<Color>("Color")
+        .register_fn("color", |r: i64, g: i64, b: i64| {
+            Color::RGB(r as u8, g as u8, b as u8)
+        })
+        .register_fn("color", |r: i64, g: i64, b: i64, a: i64| {
+            Color::RGBA(r as u8, g as u8, b as u8, a as u8)
+        })
+        .register_get("r", |color: &mut Color| color.r as i64)
+        .register_get("g", |color: &mut Color| color.g as i64)
+        .register_get("b", |color: &mut Color| color.b as i64)
+        .register_get("a", |color: &mut Color| color.a as i64)
+        .register_fn("to_string", |color: &mut Color| {
+            format!("color({}, {}, {}, {})", color.r, color.g, color.b, color.a)
+        });
+
+    // Register HSV color conversion helpers
+    engine.register_fn("hsv_to_rgb", |h: f64, s: f64, v: f64| -> Array {
+        let (r, g, b) = hsv_to_rgb(h, s, v);
+        vec![
+            Dynamic::from(r as i64),
+            Dynamic::from(g as i64),
+            Dynamic::from(b as i64),
+        ]
+    });
+
+    engine.register_fn("hsv_to_rgb_color", |h: f64, s: f64, v: f64| -> Color {
+        let (r, g, b) = hsv_to_rgb(h, s, v);
+        Color::RGB(r, g, b)
     });
 
-    // Register random number generator
-    engine.register_fn("rand", |min: i64, max: i64| -> i64 {
-        let mut rng = rand::rng();
-        rng.gen_range(min..=max)
+    engine.register_fn("rgb_to_hsv", |r: i64, g: i64, b: i64| -> Array {
+        let (h, s, v) = rgb_to_hsv(r as u8, g as u8, b as u8);
+        vec![Dynamic::from(h), Dynamic::from(s), Dynamic::from(v)]
     });
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn circle_octant_small_radii() {
+        assert_eq!(circle_octant(0), vec![(0, 0)]);
+        assert_eq!(circle_octant(1), vec![(1, 0)]);
+        assert_eq!(circle_octant(5), vec![(5, 0), (5, 1), (5, 2), (4, 3)]);
+    }
+
+    #[test]
+    fn circle_octant_stays_near_the_circle() {
+        for radius in 0..=50 {
+            let offsets = circle_octant(radius);
+            assert_eq!(offsets[0], (radius, 0));
+            for (row, &(x, y)) in offsets.iter().enumerate() {
+                assert!(x >= y && y >= 0, "radius {}: ({}, {})", radius, x, y);
+                assert_eq!(y, row as i32);
+                assert!((x * x + y * y - radius * radius).abs() <= radius);
+            }
+            // Ends at the diagonal, where the next octant takes over
+            let &(x, y) = offsets.last().unwrap();
+            assert!(x - y <= 1);
+        }
+    }
+
+    #[test]
+    fn bezier_point_interpolates_between_the_ends() {
+        let quadratic = [(0.0, 0.0), (1.0, 2.0), (2.0, 0.0)];
+        assert_eq!(bezier_point(&quadratic, 0.0), (0.0, 0.0));
+        assert_eq!(bezier_point(&quadratic, 1.0), (2.0, 0.0));
+        assert_eq!(bezier_point(&quadratic, 0.5), (1.0, 1.0));
+        // Collinear control points trace the straight line at even speed
+        let line = [(0.0, 0.0), (1.0, 1.0), (2.0, 2.0), (3.0, 3.0)];
+        assert_eq!(bezier_point(&line, 0.5), (1.5, 1.5));
+    }
+
+    #[test]
+    fn scanline_spans_cover_rows_by_their_centers() {
+        let spans = |points: &[(f32, f32)]| -> Vec<(f32, f32, f32)> {
+            scanline_spans(points)
+                .iter()
+                .map(|span| (span.x, span.y, span.w))
+                .collect()
+        };
+        // The bottom edge row at y = 3 has its center outside the square
+        let square = [(0.0, 0.0), (4.0, 0.0), (4.0, 3.0), (0.0, 3.0)];
+        assert_eq!(
+            spans(&square),
+            vec![(0.0, 0.0, 4.0), (0.0, 1.0, 4.0), (0.0, 2.0, 4.0)]
+        );
+        let triangle = [(0.0, 0.0), (4.0, 0.0), (0.0, 4.0)];
+        assert_eq!(
+            spans(&triangle),
+            vec![
+                (0.0, 0.0, 4.0),
+                (0.0, 1.0, 3.0),
+                (0.0, 2.0, 2.0),
+                (0.0, 3.0, 1.0)
+            ]
+        );
+        // Degenerate polygons have no interior
+        assert!(spans(&[(0.0, 0.0), (5.0, 5.0), (10.0, 10.0)]).is_empty());
+    }
+
+    #[test]
+    fn hsv_primaries_and_wrapping() {
+        assert_eq!(hsv_to_rgb(0.0, 1.0, 1.0), (255, 0, 0));
+        assert_eq!(hsv_to_rgb(120.0, 1.0, 1.0), (0, 255, 0));
+        assert_eq!(hsv_to_rgb(240.0, 1.0, 1.0), (0, 0, 255));
+        assert_eq!(hsv_to_rgb(360.0, 1.0, 1.0), (255, 0, 0));
+        assert_eq!(hsv_to_rgb(-120.0, 1.0, 1.0), (0, 0, 255));
+        assert_eq!(hsv_to_rgb(200.0, 0.0, 0.5), (128, 128, 128));
+        assert_eq!(rgb_to_hsv(0, 0, 0), (0.0, 0.0, 0.0));
+        assert_eq!(rgb_to_hsv(0, 255, 0), (120.0, 1.0, 1.0));
+    }
+
+    #[test]
+    fn rgb_to_hsv_round_trips() {
+        for r in (0..=255).step_by(15) {
+            for g in (0..=255).step_by(15) {
+                for b in (0..=255).step_by(15) {
+                    let (h, s, v) = rgb_to_hsv(r, g, b);
+                    assert_eq!(hsv_to_rgb(h, s, v), (r, g, b));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn rect_helpers_are_half_open() {
+        let rect = (0, 0, 10, 10);
+        assert!(rect_contains_point(rect, 0, 0));
+        assert!(rect_contains_point(rect, 9, 9));
+        assert!(!rect_contains_point(rect, 10, 5));
+        assert!(!rect_contains_point(rect, 5, -1));
+        assert!(rects_intersect(rect, (9, 9, 5, 5)));
+        assert!(rects_intersect(rect, (2, 2, 1, 1)));
+        // Touching edges and empty rects never overlap
+        assert!(!rects_intersect(rect, (10, 0, 5, 5)));
+        assert!(!rects_intersect(rect, (0, 10, 5, 5)));
+        assert!(!rects_intersect(rect, (5, 5, 0, 3)));
+    }
+
+    #[test]
+    fn easings_run_from_zero_to_one() {
+        let names = [
+            "linear",
+            "ease_in_quad",
+            "ease_out_quad",
+            "ease_in_out_quad",
+            "ease_in_cubic",
+            "ease_out_cubic",
+            "ease_in_out_cubic",
+            "ease_in_sine",
+            "ease_out_sine",
+            "ease_in_out_sine",
+            "ease_out_bounce",
+        ];
+        for name in names {
+            assert!(ease(name, 0.0).unwrap().abs() < 1e-9, "{}", name);
+            assert!((ease(name, 1.0).unwrap() - 1.0).abs() < 1e-9, "{}", name);
+            // t is clamped to [0, 1]
+            assert_eq!(ease(name, -1.0), ease(name, 0.0));
+            assert_eq!(ease(name, 2.0), ease(name, 1.0));
+        }
+        for name in ["ease_in_out_quad", "ease_in_out_cubic", "ease_in_out_sine"] {
+            assert!((ease(name, 0.5).unwrap() - 0.5).abs() < 1e-9, "{}", name);
+        }
+        assert_eq!(ease("ease_in_quad", 0.5), Ok(0.25));
+        assert!(ease("wobble", 0.5).is_err());
+    }
+
+    #[test]
+    fn spatial_grid_finds_ids_in_shared_cells() {
+        let ids =
+            |found: Array| -> Vec<i64> { found.iter().map(|id| id.as_int().unwrap()).collect() };
+        let mut grid = SpatialGrid {
+            cell_size: 10.0,
+            cells: HashMap::new(),
+        };
+        grid.insert(1, 0.0, 0.0, 5.0, 5.0);
+        // Spans cells (1, 1) to (2, 2)
+        grid.insert(2, 15.0, 15.0, 10.0, 10.0);
+        grid.insert(3, -5.0, -5.0, 1.0, 1.0);
+        assert_eq!(ids(grid.query(1.0, 1.0, 1.0, 1.0)), vec![1]);
+        assert_eq!(ids(grid.query(22.0, 22.0, 0.0, 0.0)), vec![2]);
+        assert_eq!(ids(grid.query(-1.0, -1.0, 0.0, 0.0)), vec![3]);
+        // Each id once, even when the box covers several of its cells
+        assert_eq!(ids(grid.query(0.0, 0.0, 29.0, 29.0)), vec![1, 2]);
+        assert!(grid.query(50.0, 50.0, 5.0, 5.0).is_empty());
+    }
+
+    #[test]
+    fn line_cells_walk_from_end_to_end() {
+        assert_eq!(line_cells(2, 2, 2, 2), vec![(2, 2)]);
+        assert_eq!(line_cells(0, 0, 3, 1), vec![(0, 0), (1, 0), (2, 1), (3, 1)]);
+        for &(x1, y1) in &[(7, 3), (-4, 9), (5, -5), (0, -6), (-8, -2)] {
+            let cells = line_cells(0, 0, x1, y1);
+            assert_eq!(cells.first(), Some(&(0, 0)));
+            assert_eq!(cells.last(), Some(&(x1, y1)));
+            assert_eq!(cells.len() as i64, x1.abs().max(y1.abs()) + 1);
+            // Consecutive cells touch by an edge or a corner
+            for pair in cells.windows(2) {
+                let (dx, dy) = (pair[1].0 - pair[0].0, pair[1].1 - pair[0].1);
+                assert!(dx.abs() <= 1 && dy.abs() <= 1 && (dx, dy) != (0, 0));
+            }
+        }
+    }
+
+    #[test]
+    fn map_int_rejects_values_outside_i32() {
+        let mut command = Map::new();
+        command.insert("x".into(), Dynamic::from(-7_i64));
+        command.insert("far".into(), Dynamic::from(1_i64 << 32));
+        command.insert("texture".into(), Dynamic::from((1_i64 << 32) + 1));
+        assert_eq!(map_int(&command, "x"), Ok(-7));
+        assert!(map_int(&command, "far").is_err());
+        assert!(map_int(&command, "missing").is_err());
+        // Handles are not narrowed, so this one cannot alias texture 1
+        assert_eq!(map_handle(&command, "texture"), Ok((1 << 32) + 1));
+    }
+}
@@ -0,0 +1,30 @@
+use rhai::Engine;
+use rhai_sdl3::{SDLContext, register_sdl_module};
+use std::sync::{Arc, Mutex};
+
+// SDL may only be initialized from one thread per process, so everything runs
+// in a single test. The offscreen video driver needs no display.
+#[test]
+#[allow(clippy::arc_with_non_send_sync)]
+fn circles_draw_on_a_headless_window() {
+    let sdl_context = Arc::new(Mutex::new(SDLContext::new().unwrap()));
+    let mut engine = Engine::new();
+    register_sdl_module(&mut engine, sdl_context);
+
+    let error = engine.run("fill_circle(10, 10, 5);").unwrap_err();
+    assert!(error.to_string().contains("Canvas not initialized"));
+
+    engine
+        .run(
+            r#"
+            set_hint("SDL_VIDEO_DRIVER", "offscreen");
+            create_window("circles", 64, 64);
+            set_draw_color(255, 0, 0);
+            fill_circle(32, 32, 10);
+            fill_circle(32, 32, 0);
+            draw_circle(32, 32, 20);
+            present();
+            "#,
+        )
+        .unwrap();
+}
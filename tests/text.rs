@@ -0,0 +1,56 @@
+use rhai::Engine;
+use rhai_sdl3::{SDLContext, register_sdl_module};
+use std::sync::{Arc, Mutex};
+
+const FONT: &str = concat!(
+    env!("CARGO_MANIFEST_DIR"),
+    "/tests/fonts/DejaVuSansMono.ttf"
+);
+
+// SDL may only be initialized from one thread per process, so everything runs
+// in a single test. The offscreen video driver needs no display.
+#[test]
+#[allow(clippy::arc_with_non_send_sync)]
+fn text_renders_to_an_offscreen_target() {
+    let sdl_context = Arc::new(Mutex::new(SDLContext::new().unwrap()));
+    let mut engine = Engine::new();
+    register_sdl_module(&mut engine, sdl_context);
+
+    engine
+        .run(
+            r#"
+            set_hint("SDL_VIDEO_DRIVER", "offscreen");
+            create_window("text", 128, 64);
+            "#,
+        )
+        .unwrap();
+
+    let error = engine.run(r#"load_font("missing.ttf", 16);"#).unwrap_err();
+    assert!(error.to_string().contains("missing.ttf"));
+
+    let error = engine.run(r#"draw_text(42, "hello", 0, 0);"#).unwrap_err();
+    assert!(error.to_string().contains("Invalid font handle: 42"));
+
+    // Drawing the same string twice goes through the text cache the second time
+    engine
+        .run(&format!(
+            r#"
+            let font = load_font("{FONT}", 16);
+            set_active_layer(create_layer(128, 64));
+            set_draw_color(255, 255, 255);
+            draw_text(font, "hello", 4, 4);
+            draw_text(font, "hello", 4, 24);
+            draw_text(font, "", 4, 44);
+            set_active_layer(0);
+            present();
+
+            // A new window brings a new renderer, so the cached "hello" from
+            // the old one must be rendered again rather than reused
+            create_window("text again", 128, 64);
+            set_draw_color(255, 255, 255);
+            draw_text(font, "hello", 4, 4);
+            present();
+            "#
+        ))
+        .unwrap();
+}